@@ -1,14 +1,22 @@
-use crate::eval::VM;
-use crate::value::{RefValue, Value};
+use crate::env::Env;
+use crate::error::LispError;
+use crate::eval::{eval, VM};
+use crate::number::Number;
+use crate::parser::set_dispatch_macro;
+use crate::printer::{Displayed, Sharing, Written};
+use crate::symbol::Symbol;
+use crate::value::{BuiltinFn, RefValue, Value};
 
-fn define_syntax(vm: &mut VM) -> Result<(), String> {
-    match vm.pop_pp().ok_or("syntax error")? {
+use std::convert::TryFrom;
+
+fn define_syntax(vm: &mut VM) -> Result<(), LispError> {
+    match vm.pop_pp().ok_or(LispError::Syntax("define"))? {
         // (define ident value)
         Value::Ident(ident) => {
             vm.truncate_stack();
             vm.eval_then("define2", |vm| {
-                if vm.pop_pp() != None {
-                    return Err("syntax error".to_string());
+                if vm.pop_pp().is_some() {
+                    return Err(LispError::Syntax("define"));
                 }
                 let value = vm.pop_value()?;
                 let ident = vm.pop_value()?.try_into_ident()?;
@@ -20,38 +28,127 @@ fn define_syntax(vm: &mut VM) -> Result<(), String> {
         }
         // (define (defun_ident defun_args...) body)
         Value::Cons(defun_ident, defun_args) => {
-            let body = vm.pop_pp().ok_or("syntax error")?;
-            if vm.pop_pp() != None {
-                return Err("syntax error".to_string());
+            let body = vm.pop_pp().ok_or(LispError::Syntax("define"))?;
+            if vm.pop_pp().is_some() {
+                return Err(LispError::Syntax("define"));
             }
             let defun_ident = defun_ident
                 .to_value()
                 .try_into_ident()
-                .or(Err("syntax error"))?;
+                .or(Err(LispError::Syntax("define")))?;
             let value = vm.new_closure(defun_args, RefValue::new(body));
             vm.define(defun_ident, value);
             vm.ret(Value::Bool(true))
         }
-        _ => panic!("syntax error"),
+        _ => Err(LispError::Syntax("define")),
     }
 }
 
-fn quote_syntax(vm: &mut VM) -> Result<(), String> {
-    let quoted = vm.pop_pp().ok_or("syntax error")?;
+fn quote_syntax(vm: &mut VM) -> Result<(), LispError> {
+    let quoted = vm.pop_pp().ok_or(LispError::Syntax("quote"))?;
+    quoted.mark_literal();
+    vm.ret(quoted)
+}
+
+/// `quote` for values that builtins put into the code they evaluate, which
+/// are arguments rather than constants, so they are left mutable.
+fn quote_value_syntax(vm: &mut VM) -> Result<(), LispError> {
+    let quoted = vm.pop_pp().ok_or(LispError::Syntax("quote"))?;
     vm.ret(quoted)
 }
 
-fn lambda_syntax(vm: &mut VM) -> Result<(), String> {
-    let args = vm.pop_pp().ok_or("syntax error")?;
-    let body = vm.pop_pp().ok_or("syntax error")?;
+fn quasiquote_syntax(vm: &mut VM) -> Result<(), LispError> {
+    let template = vm.pop_pp().ok_or(LispError::Syntax("quasiquote"))?;
+    if vm.pop_pp().is_some() {
+        return Err(LispError::Syntax("quasiquote"));
+    }
+    let expanded = expand_quasiquote(template, 1)?;
+    vm.truncate_stack();
+    vm.set_pp(expanded);
+    Ok(())
+}
+
+/// Rewrite a quasiquote template at nesting level `depth` into an expression
+/// that builds it. The expression calls builtins directly rather than by name,
+/// so it doesn't depend on what `cons` or `quote` are bound to.
+fn expand_quasiquote(template: Value, depth: usize) -> Result<Value, LispError> {
+    let quote = |value| Value::from_vec(vec![Value::Syntax("quote", quote_syntax), value]);
+    let cons = |car, cdr| Value::from_vec(vec![Value::Subr("cons", cons_subr), car, cdr]);
+    let list2 = |name: &str, value| {
+        cons(
+            quote(Value::Ident(Symbol::intern(name))),
+            cons(value, quote(Value::Null)),
+        )
+    };
+
+    let (car, cdr) = match template {
+        Value::Cons(car, cdr) => (car.to_value(), cdr.to_value()),
+        Value::Vector(vector) => {
+            let items = Value::from_vec(vector.borrow().clone());
+            return Ok(Value::from_vec(vec![
+                Value::Subr("list->vector", list_to_vector_subr),
+                expand_quasiquote(items, depth)?,
+            ]));
+        }
+        atom => return Ok(quote(atom)),
+    };
+    match (car, unary_form_operand(&cdr)) {
+        (Value::Ident(name), Some(operand)) if name.name() == "unquote" => {
+            if depth == 1 {
+                Ok(operand)
+            } else {
+                Ok(list2("unquote", expand_quasiquote(operand, depth - 1)?))
+            }
+        }
+        (Value::Ident(name), Some(operand)) if name.name() == "quasiquote" => {
+            Ok(list2("quasiquote", expand_quasiquote(operand, depth + 1)?))
+        }
+        (Value::Ident(name), Some(_)) if name.name() == "unquote-splicing" => {
+            Err(LispError::Syntax("unquote-splicing"))
+        }
+        (car, _) => {
+            let rest = expand_quasiquote(cdr, depth)?;
+            if let Value::Cons(head, tail) = &car {
+                let operand = unary_form_operand(&tail.to_value());
+                if let (Value::Ident(name), Some(operand)) = (head.to_value(), operand) {
+                    if name.name() == "unquote-splicing" {
+                        return if depth == 1 {
+                            Ok(Value::from_vec(vec![
+                                Value::Subr("append", append_subr),
+                                operand,
+                                rest,
+                            ]))
+                        } else {
+                            let operand = expand_quasiquote(operand, depth - 1)?;
+                            Ok(cons(list2("unquote-splicing", operand), rest))
+                        };
+                    }
+                }
+            }
+            Ok(cons(expand_quasiquote(car, depth)?, rest))
+        }
+    }
+}
+
+/// If `args` is a one-element list, return the element.
+fn unary_form_operand(args: &Value) -> Option<Value> {
+    match args {
+        Value::Cons(car, cdr) if cdr.to_value() == Value::Null => Some(car.to_value()),
+        _ => None,
+    }
+}
+
+fn lambda_syntax(vm: &mut VM) -> Result<(), LispError> {
+    let args = vm.pop_pp().ok_or(LispError::Syntax("lambda"))?;
+    let body = vm.pop_pp().ok_or(LispError::Syntax("lambda"))?;
     vm.ret(vm.new_closure(RefValue::new(args), RefValue::new(body)))
 }
 
-fn if_syntax(vm: &mut VM) -> Result<(), String> {
+fn if_syntax(vm: &mut VM) -> Result<(), LispError> {
     vm.truncate_stack();
     vm.eval_then("if2", |vm| {
         let test = vm.pop_value()?.try_into_bool()?;
-        let then_expr = vm.pop_pp().ok_or("syntax error")?;
+        let then_expr = vm.pop_pp().ok_or(LispError::Syntax("if"))?;
         let else_expr = vm.pop_pp().unwrap_or(Value::Null);
         if test {
             vm.set_pp(then_expr);
@@ -64,14 +161,14 @@ fn if_syntax(vm: &mut VM) -> Result<(), String> {
     Ok(())
 }
 
-fn call_cc_syntax(vm: &mut VM) -> Result<(), String> {
+fn call_cc_syntax(vm: &mut VM) -> Result<(), LispError> {
     vm.truncate_stack();
     vm.eval_then("call/cc2", |vm| {
         let cont = Value::Cont(Box::new(vm.clone()));
         let lambda = vm.pop_value()?;
         if let Value::Closure(_, _, _) = lambda {
         } else {
-            return Err("syntax error".to_string());
+            return Err(lambda.type_error("procedure"));
         }
         vm.pop_value()?; // pop 'call/cc ?
         vm.push_value(lambda);
@@ -81,31 +178,63 @@ fn call_cc_syntax(vm: &mut VM) -> Result<(), String> {
     Ok(())
 }
 
-fn cons_subr(vm: &mut VM) -> Result<(), String> {
-    let mut args = vm.args();
-    let car = args.next().ok_or("syntax error")??;
-    let cdr = args.next().ok_or("syntax error")??;
-    std::mem::drop(args);
+fn cons_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(2..=2)?;
+    let car = vm.arg(0)?;
+    let cdr = vm.arg(1)?;
     vm.ret(Value::Cons(RefValue::new(car), RefValue::new(cdr)))
 }
 
-fn car_subr(vm: &mut VM) -> Result<(), String> {
-    let mut args = vm.args();
-    let cons = args.next().ok_or("syntax error")??.try_into_cons()?;
-    std::mem::drop(args);
+fn car_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let cons = vm.arg(0)?.try_into_cons()?;
     vm.ret(cons.0)
 }
 
-fn cdr_subr(vm: &mut VM) -> Result<(), String> {
-    let mut args = vm.args();
-    let cons = args.next().ok_or("syntax error")??.try_into_cons()?;
-    std::mem::drop(args);
+fn cdr_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let cons = vm.arg(0)?.try_into_cons()?;
     vm.ret(cons.1)
 }
 
-fn eqv_subr(vm: &mut VM) -> Result<(), String> {
+fn set_car_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(2..=2)?;
+    match vm.arg(0)? {
+        Value::Cons(car, _) => car.set(vm.arg(1)?)?,
+        other => return Err(other.type_error("pair")),
+    }
+    vm.ret(Value::Bool(true))
+}
+
+fn set_cdr_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(2..=2)?;
+    match vm.arg(0)? {
+        Value::Cons(_, cdr) => cdr.set(vm.arg(1)?)?,
+        other => return Err(other.type_error("pair")),
+    }
+    vm.ret(Value::Bool(true))
+}
+
+fn append_subr(vm: &mut VM) -> Result<(), LispError> {
+    let mut args = vm.args().collect::<Result<Vec<_>, _>>()?;
+    let mut result = args.pop().unwrap_or(Value::Null);
+    for list in args.into_iter().rev() {
+        let mut items = Vec::new();
+        let mut rest = list;
+        while let Value::Cons(car, cdr) = rest {
+            items.push(car.to_value());
+            rest = cdr.to_value();
+        }
+        rest.try_into_nil()?;
+        result = Value::from_vec_with_tail(items, result);
+    }
+    vm.ret(result)
+}
+
+fn eqv_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=usize::MAX)?;
     let mut args = vm.args();
-    let first = args.next().ok_or("syntax error")??;
+    let first = args.next().unwrap()?;
     let mut result = true;
     for val in args {
         if first != val? {
@@ -116,83 +245,554 @@ fn eqv_subr(vm: &mut VM) -> Result<(), String> {
     vm.ret(Value::Bool(result))
 }
 
-fn equal_subr(vm: &mut VM) -> Result<(), String> {
+fn equal_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=usize::MAX)?;
     let mut args = vm.args();
-    let first = args.next().ok_or("syntax error")??;
+    let first = args.next().unwrap()?.try_into_number()?;
     let mut result = true;
     for val in args {
-        if first != val? {
-            result = false;
-            break;
-        }
+        // check the types of the rest even once the answer is known
+        result = first.num_eq(&val?.try_into_number()?) && result;
     }
     vm.ret(Value::Bool(result))
 }
 
-fn plus_subr(vm: &mut VM) -> Result<(), String> {
-    let mut acc = 0.0;
+fn plus_subr(vm: &mut VM) -> Result<(), LispError> {
+    let mut acc = Number::Int(0);
     for val in vm.args() {
-        acc += val?.try_into_num()?;
+        acc = acc + val?.try_into_number()?;
     }
-    vm.ret(Value::Num(acc))
+    vm.ret(Value::from(acc))
 }
 
-fn minus_subr(vm: &mut VM) -> Result<(), String> {
+fn minus_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=usize::MAX)?;
+    if vm.arg_count() == 1 {
+        let num = vm.arg(0)?.try_into_number()?;
+        return vm.ret(Value::from(-num));
+    }
     let mut args = vm.args();
-    let mut acc = args.next().ok_or("syntax error")??.try_into_num()?;
+    let mut acc = args.next().unwrap()?.try_into_number()?;
     for val in args {
-        acc -= val?.try_into_num()?;
+        acc = acc - val?.try_into_number()?;
     }
-    vm.ret(Value::Num(acc))
+    vm.ret(Value::from(acc))
 }
 
-fn multiply_subr(vm: &mut VM) -> Result<(), String> {
-    let mut acc = 1.0;
+fn multiply_subr(vm: &mut VM) -> Result<(), LispError> {
+    let mut acc = Number::Int(1);
     for val in vm.args() {
-        acc *= val?.try_into_num()?;
+        acc = acc * val?.try_into_number()?;
     }
-    vm.ret(Value::Num(acc))
+    vm.ret(Value::from(acc))
 }
 
-fn divide_subr(vm: &mut VM) -> Result<(), String> {
+fn divide_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=usize::MAX)?;
+    if vm.arg_count() == 1 {
+        let num = vm.arg(0)?.try_into_number()?;
+        return vm.ret(Value::from(Number::Int(1).try_div(num)?));
+    }
     let mut args = vm.args();
-    let mut acc = args.next().ok_or("syntax error")??.try_into_num()?;
+    let mut acc = args.next().unwrap()?.try_into_number()?;
     for val in args {
-        acc /= val?.try_into_num()?;
+        acc = acc.try_div(val?.try_into_number()?)?;
     }
-    vm.ret(Value::Num(acc))
+    vm.ret(Value::from(acc))
 }
 
-fn print_subr(vm: &mut VM) -> Result<(), String> {
+/// Apply `op` to the two numeric arguments.
+fn binary_number_op(
+    vm: &mut VM,
+    op: fn(Number, Number) -> Result<Number, LispError>,
+) -> Result<(), LispError> {
+    vm.check_arity(2..=2)?;
+    let a = vm.arg(0)?.try_into_number()?;
+    let b = vm.arg(1)?.try_into_number()?;
+    vm.ret(Value::from(op(a, b)?))
+}
+
+fn quotient_subr(vm: &mut VM) -> Result<(), LispError> {
+    binary_number_op(vm, Number::quotient)
+}
+
+fn remainder_subr(vm: &mut VM) -> Result<(), LispError> {
+    binary_number_op(vm, Number::remainder)
+}
+
+fn modulo_subr(vm: &mut VM) -> Result<(), LispError> {
+    binary_number_op(vm, Number::modulo)
+}
+
+fn expt_subr(vm: &mut VM) -> Result<(), LispError> {
+    binary_number_op(vm, Number::expt)
+}
+
+fn numerator_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let num = vm.arg(0)?.try_into_number()?;
+    vm.ret(Value::from(num.numerator()?))
+}
+
+fn denominator_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let num = vm.arg(0)?.try_into_number()?;
+    vm.ret(Value::from(num.denominator()?))
+}
+
+fn rationalize_subr(vm: &mut VM) -> Result<(), LispError> {
+    binary_number_op(vm, Number::rationalize)
+}
+
+fn gcd_subr(vm: &mut VM) -> Result<(), LispError> {
+    let mut acc = Number::Int(0);
     for val in vm.args() {
-        println!("{:?}", val?);
+        acc = acc.gcd(val?.try_into_number()?)?;
+    }
+    vm.ret(Value::from(acc))
+}
+
+fn exact_p_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let num = vm.arg(0)?.try_into_number()?;
+    vm.ret(Value::Bool(num.is_exact()))
+}
+
+fn inexact_p_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let num = vm.arg(0)?.try_into_number()?;
+    vm.ret(Value::Bool(!num.is_exact()))
+}
+
+fn exact_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let num = vm.arg(0)?.try_into_number()?;
+    vm.ret(Value::from(num.into_exact()?))
+}
+
+fn inexact_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let num = vm.arg(0)?.try_into_number()?;
+    vm.ret(Value::from(num.into_inexact()))
+}
+
+fn char_p_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let result = matches!(vm.arg(0)?, Value::Char(_));
+    vm.ret(Value::Bool(result))
+}
+
+fn char_to_integer_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let ch = vm.arg(0)?.try_into_char()?;
+    vm.ret(Value::Int(ch as i64))
+}
+
+fn integer_to_char_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let num = vm.arg(0)?.try_into_int()?;
+    let ch = u32::try_from(num)
+        .ok()
+        .and_then(std::char::from_u32)
+        .ok_or_else(|| LispError::InvalidArgument(format!("invalid code point: {}", num)))?;
+    vm.ret(Value::Char(ch))
+}
+
+fn char_compare(vm: &mut VM, cmp: fn(char, char) -> bool) -> Result<(), LispError> {
+    vm.check_arity(1..=usize::MAX)?;
+    let mut args = vm.args();
+    let mut prev = args.next().unwrap()?.try_into_char()?;
+    let mut result = true;
+    for val in args {
+        let ch = val?.try_into_char()?;
+        result = result && cmp(prev, ch);
+        prev = ch;
     }
+    vm.ret(Value::Bool(result))
+}
+
+fn char_eq_subr(vm: &mut VM) -> Result<(), LispError> {
+    char_compare(vm, |a, b| a == b)
+}
+
+fn char_lt_subr(vm: &mut VM) -> Result<(), LispError> {
+    char_compare(vm, |a, b| a < b)
+}
+
+/// Convert `value` to an index that must be less than `len`.
+fn try_into_index(value: Value, len: usize) -> Result<usize, LispError> {
+    let num = value.try_into_int()?;
+    match usize::try_from(num) {
+        Ok(index) if index < len => Ok(index),
+        _ => Err(LispError::InvalidArgument(format!(
+            "index out of range: {}",
+            num
+        ))),
+    }
+}
+
+/// Read the optional `start` and `end` arguments at `index` and `index + 1`
+/// that select part of a sequence of length `len`. They default to the whole.
+fn optional_range(vm: &VM, index: usize, len: usize) -> Result<(usize, usize), LispError> {
+    let start = if vm.arg_count() > index {
+        try_into_index(vm.arg(index)?, len + 1)?
+    } else {
+        0
+    };
+    let end = if vm.arg_count() > index + 1 {
+        try_into_index(vm.arg(index + 1)?, len + 1)?
+    } else {
+        len
+    };
+    if start > end {
+        return Err(LispError::InvalidArgument(format!(
+            "start {} is after end {}",
+            start, end
+        )));
+    }
+    Ok((start, end))
+}
+
+fn vector_subr(vm: &mut VM) -> Result<(), LispError> {
+    let items = vm.args().collect::<Result<Vec<_>, _>>()?;
+    vm.ret(Value::new_vector(items))
+}
+
+fn make_vector_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=2)?;
+    let len = vm.arg(0)?.try_into_int()?;
+    let fill = if vm.arg_count() > 1 {
+        vm.arg(1)?
+    } else {
+        Value::Bool(false)
+    };
+    let len = usize::try_from(len).map_err(|_| {
+        LispError::InvalidArgument(format!("invalid vector length: {}", len))
+    })?;
+    vm.ret(Value::new_vector(filled_vec(fill, len, "vector")?))
+}
+
+/// `len` copies of `fill`, or an error naming `what` if they don't fit in
+/// memory, rather than the panic of `vec!`.
+fn filled_vec<T: Clone>(fill: T, len: usize, what: &str) -> Result<Vec<T>, LispError> {
+    let mut items = Vec::new();
+    items.try_reserve_exact(len).map_err(|_| {
+        LispError::InvalidArgument(format!("{} too large: {}", what, len))
+    })?;
+    items.resize(len, fill);
+    Ok(items)
+}
+
+fn vector_ref_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(2..=2)?;
+    let vector = vm.arg(0)?.try_into_vector()?;
+    let index = try_into_index(vm.arg(1)?, vector.borrow().len())?;
+    let value = vector.borrow()[index].clone();
+    vm.ret(value)
+}
+
+fn vector_set_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(3..=3)?;
+    let vector = vm.arg(0)?.try_into_vector()?;
+    let index = try_into_index(vm.arg(1)?, vector.borrow().len())?;
+    vector.borrow_mut()[index] = vm.arg(2)?;
+    vm.ret(Value::Bool(true))
+}
+
+fn vector_length_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let len = vm.arg(0)?.try_into_vector()?.borrow().len();
+    vm.ret(Value::Int(len as i64))
+}
+
+fn vector_to_list_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let items = vm.arg(0)?.try_into_vector()?.borrow().clone();
+    vm.ret(Value::from_vec(items))
+}
+
+fn list_to_vector_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let mut items = Vec::new();
+    let mut rest = vm.arg(0)?;
+    while let Value::Cons(car, cdr) = rest {
+        items.push(car.to_value());
+        rest = cdr.to_value();
+    }
+    rest.try_into_nil()?;
+    vm.ret(Value::new_vector(items))
+}
+
+fn vector_fill_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(2..=4)?;
+    let vector = vm.arg(0)?.try_into_vector()?;
+    let fill = vm.arg(1)?;
+    let (start, end) = optional_range(vm, 2, vector.borrow().len())?;
+    for value in &mut vector.borrow_mut()[start..end] {
+        *value = fill.clone();
+    }
+    vm.ret(Value::Bool(true))
+}
+
+fn vector_map_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(2..=usize::MAX)?;
+    let mut args = vm.args();
+    let procedure = args.next().unwrap()?;
+    let vectors = args
+        .map(|arg| arg?.try_into_vector())
+        .collect::<Result<Vec<_>, _>>()?;
+    // Evaluate `(vector (procedure 'a0 'b0 ...) (procedure 'a1 'b1 ...) ...)`
+    // in place of this call.
+    let len = vectors.iter().map(|v| v.borrow().len()).min().unwrap_or(0);
+    let mut calls = vec![Value::Subr("vector", vector_subr)];
+    for i in 0..len {
+        let mut call = vec![procedure.clone()];
+        for vector in &vectors {
+            let item = vector.borrow()[i].clone();
+            call.push(Value::from_vec(vec![
+                Value::Syntax("quote", quote_value_syntax),
+                item,
+            ]));
+        }
+        calls.push(Value::from_vec(call));
+    }
+    vm.truncate_stack();
+    vm.set_pp(Value::from_vec(calls));
+    Ok(())
+}
+
+/// Convert `value` to a byte, an exact integer from 0 to 255.
+fn try_into_byte(value: Value) -> Result<u8, LispError> {
+    let num = value.try_into_int()?;
+    u8::try_from(num).map_err(|_| LispError::InvalidArgument(format!("invalid byte: {}", num)))
+}
+
+fn bytevector_subr(vm: &mut VM) -> Result<(), LispError> {
+    let bytes = vm
+        .args()
+        .map(|arg| try_into_byte(arg?))
+        .collect::<Result<Vec<_>, _>>()?;
+    vm.ret(Value::new_bytevector(bytes))
+}
+
+fn make_bytevector_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=2)?;
+    let len = vm.arg(0)?.try_into_int()?;
+    let fill = if vm.arg_count() > 1 {
+        try_into_byte(vm.arg(1)?)?
+    } else {
+        0
+    };
+    let len = usize::try_from(len).map_err(|_| {
+        LispError::InvalidArgument(format!("invalid bytevector length: {}", len))
+    })?;
+    vm.ret(Value::new_bytevector(filled_vec(fill, len, "bytevector")?))
+}
+
+fn bytevector_u8_ref_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(2..=2)?;
+    let bytes = vm.arg(0)?.try_into_bytevector()?;
+    let index = try_into_index(vm.arg(1)?, bytes.borrow().len())?;
+    let byte = bytes.borrow()[index];
+    vm.ret(Value::Int(byte as i64))
+}
+
+fn bytevector_u8_set_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(3..=3)?;
+    let bytes = vm.arg(0)?.try_into_bytevector()?;
+    let index = try_into_index(vm.arg(1)?, bytes.borrow().len())?;
+    bytes.borrow_mut()[index] = try_into_byte(vm.arg(2)?)?;
+    vm.ret(Value::Bool(true))
+}
+
+fn bytevector_length_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let len = vm.arg(0)?.try_into_bytevector()?.borrow().len();
+    vm.ret(Value::Int(len as i64))
+}
+
+fn bytevector_copy_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=3)?;
+    let bytes = vm.arg(0)?.try_into_bytevector()?;
+    let (start, end) = optional_range(vm, 1, bytes.borrow().len())?;
+    let copy = bytes.borrow()[start..end].to_vec();
+    vm.ret(Value::new_bytevector(copy))
+}
+
+fn bytevector_append_subr(vm: &mut VM) -> Result<(), LispError> {
+    let mut bytes = Vec::new();
+    for arg in vm.args() {
+        bytes.extend_from_slice(&arg?.try_into_bytevector()?.borrow());
+    }
+    vm.ret(Value::new_bytevector(bytes))
+}
+
+fn utf8_to_string_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=3)?;
+    let bytes = vm.arg(0)?.try_into_bytevector()?;
+    let (start, end) = optional_range(vm, 1, bytes.borrow().len())?;
+    let string = String::from_utf8(bytes.borrow()[start..end].to_vec())
+        .map_err(|e| LispError::InvalidArgument(format!("invalid UTF-8: {}", e)))?;
+    vm.ret(Value::Str(string))
+}
+
+fn string_to_utf8_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=3)?;
+    let string = vm.arg(0)?.try_into_str()?;
+    let (start, end) = optional_range(vm, 1, string.chars().count())?;
+    let part = string.chars().skip(start).take(end - start).collect::<String>();
+    vm.ret(Value::new_bytevector(part.into_bytes()))
+}
+
+fn symbol_to_string_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let symbol = vm.arg(0)?.try_into_ident()?;
+    vm.ret(Value::Str(symbol.to_string()))
+}
+
+fn string_to_symbol_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    let string = vm.arg(0)?.try_into_str()?;
+    vm.ret(Value::Ident(Symbol::intern(&string)))
+}
+
+fn gensym_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(0..=0)?;
+    vm.ret(Value::Ident(Symbol::gensym()))
+}
+
+/// Display each argument on a line of its own.
+fn print_subr(vm: &mut VM) -> Result<(), LispError> {
+    for val in vm.args() {
+        println!("{}", Displayed(&val?));
+    }
+    vm.ret(Value::Bool(true))
+}
+
+fn set_dispatch_macro_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(2..=2)?;
+    set_dispatch_macro(vm.arg(0)?.try_into_char()?, vm.arg(1)?)?;
+    vm.ret(Value::Bool(true))
+}
+
+fn write_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    print!("{}", Written::new(&vm.arg(0)?, Sharing::Cycles));
+    vm.ret(Value::Bool(true))
+}
+
+fn write_shared_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    print!("{}", Written::new(&vm.arg(0)?, Sharing::All));
+    vm.ret(Value::Bool(true))
+}
+
+fn display_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    print!("{}", Displayed(&vm.arg(0)?));
     vm.ret(Value::Bool(true))
 }
 
-fn print_env_subr(vm: &mut VM) -> Result<(), String> {
+fn newline_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(0..=0)?;
+    println!();
+    vm.ret(Value::Bool(true))
+}
+
+fn write_string_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    print!("{}", vm.arg(0)?.try_into_str()?);
+    vm.ret(Value::Bool(true))
+}
+
+fn write_char_subr(vm: &mut VM) -> Result<(), LispError> {
+    vm.check_arity(1..=1)?;
+    print!("{}", vm.arg(0)?.try_into_char()?);
+    vm.ret(Value::Bool(true))
+}
+
+fn print_env_subr(vm: &mut VM) -> Result<(), LispError> {
     vm.print_env();
     vm.ret(Value::Bool(true))
 }
 
-pub static SYNTAX: &[(&str, fn(&mut VM) -> Result<(), String>)] = &[
+/// Call `procedure` on `args` outside of any running program, as the reader
+/// does for reader macros.
+pub fn apply(procedure: Value, args: Vec<Value>) -> Result<Value, LispError> {
+    let quote = |arg| Value::from_vec(vec![Value::Syntax("quote", quote_value_syntax), arg]);
+    let args = Value::from_vec(args.into_iter().map(quote).collect());
+    eval(Value::from_vec_with_tail(vec![procedure], args), Env::new(None))
+}
+
+pub static SYNTAX: &[(&str, BuiltinFn)] = &[
     ("define", define_syntax),
     ("quote", quote_syntax),
+    ("quasiquote", quasiquote_syntax),
     ("lambda", lambda_syntax),
     ("if", if_syntax),
     ("call/cc", call_cc_syntax),
 ];
 
-pub static SUBR: &[(&str, fn(&mut VM) -> Result<(), String>)] = &[
+pub static SUBR: &[(&str, BuiltinFn)] = &[
     ("cons", cons_subr),
     ("car", car_subr),
     ("cdr", cdr_subr),
+    ("set-car!", set_car_subr),
+    ("set-cdr!", set_cdr_subr),
+    ("append", append_subr),
+    // symbols are interned, so `eqv?` already compares them by pointer
+    ("eq?", eqv_subr),
     ("eqv?", eqv_subr),
     ("=", equal_subr),
     ("+", plus_subr),
     ("-", minus_subr),
     ("*", multiply_subr),
     ("/", divide_subr),
+    ("quotient", quotient_subr),
+    ("remainder", remainder_subr),
+    ("modulo", modulo_subr),
+    ("expt", expt_subr),
+    ("gcd", gcd_subr),
+    ("numerator", numerator_subr),
+    ("denominator", denominator_subr),
+    ("rationalize", rationalize_subr),
+    ("exact?", exact_p_subr),
+    ("inexact?", inexact_p_subr),
+    ("exact", exact_subr),
+    ("inexact", inexact_subr),
+    ("char?", char_p_subr),
+    ("char->integer", char_to_integer_subr),
+    ("integer->char", integer_to_char_subr),
+    ("char=?", char_eq_subr),
+    ("char<?", char_lt_subr),
+    ("vector", vector_subr),
+    ("make-vector", make_vector_subr),
+    ("vector-ref", vector_ref_subr),
+    ("vector-set!", vector_set_subr),
+    ("vector-length", vector_length_subr),
+    ("vector->list", vector_to_list_subr),
+    ("list->vector", list_to_vector_subr),
+    ("vector-fill!", vector_fill_subr),
+    ("vector-map", vector_map_subr),
+    ("bytevector", bytevector_subr),
+    ("make-bytevector", make_bytevector_subr),
+    ("bytevector-u8-ref", bytevector_u8_ref_subr),
+    ("bytevector-u8-set!", bytevector_u8_set_subr),
+    ("bytevector-length", bytevector_length_subr),
+    ("bytevector-copy", bytevector_copy_subr),
+    ("bytevector-append", bytevector_append_subr),
+    ("utf8->string", utf8_to_string_subr),
+    ("string->utf8", string_to_utf8_subr),
+    ("symbol->string", symbol_to_string_subr),
+    ("string->symbol", string_to_symbol_subr),
+    ("gensym", gensym_subr),
+    ("set-dispatch-macro!", set_dispatch_macro_subr),
     ("print", print_subr),
+    ("write", write_subr),
+    ("write-shared", write_shared_subr),
+    ("display", display_subr),
+    ("newline", newline_subr),
+    ("write-string", write_string_subr),
+    ("write-char", write_char_subr),
     ("print-env", print_env_subr),
 ];
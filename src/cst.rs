@@ -0,0 +1,218 @@
+use crate::error::LispError;
+use crate::lexer::{Lexer, Span, Token};
+
+use std::fmt;
+use std::iter::Peekable;
+use std::vec;
+
+/// A token with the source text it was read from.
+#[derive(Debug, Clone)]
+pub struct CstToken {
+    /// The whitespace and comments before the token, verbatim.
+    pub trivia: String,
+    /// The token, or the error for text that isn't one.
+    pub token: Result<Token, LispError>,
+    /// The text of the token itself, verbatim.
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum CstNode {
+    /// An atom, or a token that doesn't fit anywhere, like a stray `)`.
+    Token(CstToken),
+    /// A sequence in brackets: a list, vector, bytevector or curly-infix list.
+    /// `close` is `None` if the input ends first.
+    List {
+        open: CstToken,
+        items: Vec<CstNode>,
+        close: Option<CstToken>,
+    },
+    /// A token that applies to the datum after it: an abbreviation like `'`,
+    /// `#;`, a label `#n=` or a reader macro. `datum` is `None` if nothing follows.
+    Prefix {
+        prefix: CstToken,
+        datum: Option<Box<CstNode>>,
+    },
+}
+
+/// The lossless concrete syntax tree of a whole source, for tools like
+/// formatters that need the source exactly as written. Printing it
+/// reproduces the source.
+#[derive(Debug, Clone)]
+pub struct Cst {
+    pub nodes: Vec<CstNode>,
+    /// The whitespace and comments after the last token.
+    pub trailing_trivia: String,
+}
+
+impl Cst {
+    /// Read the concrete syntax tree of `chars`, naming `file` in its spans.
+    /// Malformed input is kept as error tokens rather than rejected.
+    pub fn read<C: Iterator<Item = char>>(chars: C, file: &str) -> Cst {
+        let mut lexer = Lexer::new(chars, file).lossless();
+        let mut tokens = Vec::new();
+        while let Some(item) = lexer.next() {
+            let (trivia, text) = lexer.take_text();
+            let (token, span) = match item {
+                Ok((token, span)) => (Ok(token), span),
+                Err(LispError::At(span, e)) => (Err(*e), span),
+                Err(e) => unreachable!("lexer error without a location: {}", e),
+            };
+            tokens.push(CstToken {
+                trivia,
+                token,
+                text,
+                span,
+            });
+        }
+        let (trailing_trivia, _) = lexer.take_text();
+
+        let mut tokens = tokens.into_iter().peekable();
+        let mut nodes = Vec::new();
+        while let Some(node) = read_node(&mut tokens) {
+            nodes.push(node);
+        }
+        Cst {
+            nodes,
+            trailing_trivia,
+        }
+    }
+}
+
+fn read_node(tokens: &mut Peekable<vec::IntoIter<CstToken>>) -> Option<CstNode> {
+    let token = tokens.next()?;
+    Some(match token.token {
+        Ok(Token::LPER)
+        | Ok(Token::NEOLPER)
+        | Ok(Token::LVECTOR)
+        | Ok(Token::LBYTEVECTOR)
+        | Ok(Token::LBRACE)
+        | Ok(Token::NEOLBRACE) => {
+            let mut items = Vec::new();
+            while tokens.peek().is_some() && !is_close(tokens.peek()) {
+                items.extend(read_node(tokens));
+            }
+            CstNode::List {
+                open: token,
+                items,
+                close: tokens.next(),
+            }
+        }
+        Ok(Token::QUOTE)
+        | Ok(Token::QUASIQUOTE)
+        | Ok(Token::UNQUOTE)
+        | Ok(Token::UNQUOTESPLICING)
+        | Ok(Token::DATUMCOMMENT)
+        | Ok(Token::LABELDEF(_))
+        | Ok(Token::DISPATCH(_)) => {
+            let datum = if is_close(tokens.peek()) {
+                None
+            } else {
+                read_node(tokens).map(Box::new)
+            };
+            CstNode::Prefix {
+                prefix: token,
+                datum,
+            }
+        }
+        _ => CstNode::Token(token),
+    })
+}
+
+fn is_close(token: Option<&CstToken>) -> bool {
+    matches!(
+        token.map(|token| &token.token),
+        Some(Ok(Token::RPER)) | Some(Ok(Token::RBRACE))
+    )
+}
+
+impl fmt::Display for CstToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.trivia, self.text)
+    }
+}
+
+impl fmt::Display for CstNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CstNode::Token(token) => write!(f, "{}", token),
+            CstNode::List { open, items, close } => {
+                write!(f, "{}", open)?;
+                for item in items {
+                    write!(f, "{}", item)?;
+                }
+                match close {
+                    Some(close) => write!(f, "{}", close),
+                    None => Ok(()),
+                }
+            }
+            CstNode::Prefix { prefix, datum } => {
+                write!(f, "{}", prefix)?;
+                match datum {
+                    Some(datum) => write!(f, "{}", datum),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for node in &self.nodes {
+            write!(f, "{}", node)?;
+        }
+        write!(f, "{}", self.trailing_trivia)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::VM;
+    use crate::parser::set_dispatch_macro;
+    use crate::value::Value;
+
+    fn assert_reprints(source: &str) {
+        assert_eq!(Cst::read(source.chars(), "<string>").to_string(), source);
+    }
+
+    fn identity(vm: &mut VM) -> Result<(), LispError> {
+        let datum = vm.arg(0)?;
+        vm.ret(datum)
+    }
+
+    #[test]
+    fn reprints_crlf() {
+        assert_reprints("(define x\r\n  \"a\\\r\n  b\")\r\n; done\r\n");
+    }
+
+    #[test]
+    fn reprints_comments() {
+        assert_reprints("#| outer #| inner |# still |#\n(a #;(b c) d) ; end");
+    }
+
+    #[test]
+    fn reprints_labels() {
+        assert_reprints("#0=(a . #0#) #1=#(x #1#)");
+    }
+
+    #[test]
+    fn reprints_reader_macro_prefixes() {
+        set_dispatch_macro('q', Value::Subr("identity", identity)).unwrap();
+        assert_reprints("'a `(b ,c ,@d) #q (e) #q\tf");
+    }
+
+    #[test]
+    fn reprints_malformed_tokens() {
+        assert_reprints("(#e1.2.3 #u8 (1) \"bad \\q escape\" #zebra #\\nope) )");
+    }
+
+    #[test]
+    fn reprints_unterminated_input() {
+        assert_reprints("(a (b \"c");
+        assert_reprints("#| open");
+        assert_reprints("(a\n  #;");
+    }
+}
@@ -1,4 +1,5 @@
 use crate::builtins::{SUBR, SYNTAX};
+use crate::symbol::Symbol;
 use crate::value::Value;
 
 use std::cell::RefCell;
@@ -6,7 +7,7 @@ use std::collections::HashMap;
 use std::rc::Rc;
 
 struct ChainMapCell<T: Clone> {
-    inner: HashMap<String, T>,
+    inner: HashMap<Symbol, T>,
     outer: Option<ChainMap<T>>,
 }
 
@@ -17,7 +18,7 @@ impl<T: Clone> ChainMap<T> {
     pub fn new(outer: Option<ChainMap<T>>) -> ChainMap<T> {
         ChainMap(Rc::new(RefCell::new(ChainMapCell {
             inner: HashMap::new(),
-            outer: outer,
+            outer,
         })))
     }
 
@@ -25,11 +26,11 @@ impl<T: Clone> ChainMap<T> {
         Self::new(Some(ChainMap(self.0.clone())))
     }
 
-    pub fn insert(&self, key: String, value: T) {
+    pub fn insert(&self, key: Symbol, value: T) {
         self.0.borrow_mut().inner.insert(key, value);
     }
 
-    pub fn get(&self, key: String) -> Option<T> {
+    pub fn get(&self, key: Symbol) -> Option<T> {
         if let Some(value) = self.0.borrow().inner.get(&key) {
             Some(value.clone())
         } else {
@@ -71,10 +72,10 @@ impl Env {
     pub fn new_default() -> Env {
         let env = Env::new(None);
         for &(name, f) in SYNTAX {
-            env.insert(name.to_string(), Value::Syntax(name, f));
+            env.insert(Symbol::intern(name), Value::Syntax(name, f));
         }
         for &(name, f) in SUBR {
-            env.insert(name.to_string(), Value::Subr(name, f));
+            env.insert(Symbol::intern(name), Value::Subr(name, f));
         }
         env
     }
@@ -0,0 +1,98 @@
+use crate::lexer::Span;
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// An error raised while reading or evaluating lisp code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LispError {
+    /// Failure to read the source, like invalid UTF-8 in a file.
+    Io(String),
+    /// Malformed token, like an unterminated string or a bad number.
+    Lex(String),
+    /// Tokens that don't form a datum, like a stray `)`.
+    Parse(String),
+    /// Input that ends in the middle of a datum, like an unclosed list. More
+    /// input could make it complete.
+    Incomplete(String),
+    /// Malformed special form, named by the form.
+    Syntax(&'static str),
+    Unbound {
+        name: String,
+    },
+    Type {
+        expected: &'static str,
+        got: &'static str,
+    },
+    Arity {
+        expected: RangeInclusive<usize>,
+        got: usize,
+    },
+    /// Application of something that isn't a procedure, written out.
+    NotCallable(String),
+    /// An argument of the right type but an unacceptable value.
+    InvalidArgument(String),
+    Internal(String),
+    /// An error raised by the reader macro for `#ch`, located where the
+    /// macro's code raised it.
+    ReaderMacro(char, Box<LispError>),
+    /// An error together with the location of the code that raised it.
+    At(Span, Box<LispError>),
+}
+
+impl LispError {
+    /// Attach `span` to this error unless it already has a location.
+    pub fn at(self, span: Span) -> LispError {
+        match self {
+            LispError::At(_, _) => self,
+            error => LispError::At(span, Box::new(error)),
+        }
+    }
+
+    /// The error without its location.
+    pub fn kind(&self) -> &LispError {
+        match self {
+            LispError::At(_, error) => error.kind(),
+            error => error,
+        }
+    }
+
+    /// Where the error was raised, if known.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            LispError::At(span, _) => Some(span),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LispError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LispError::Lex(message) => write!(f, "lexer error: {}", message),
+            LispError::Parse(message) | LispError::Incomplete(message) => {
+                write!(f, "syntax error: {}", message)
+            }
+            LispError::Syntax(form) => write!(f, "syntax error in {}", form),
+            LispError::Unbound { name } => write!(f, "unbound variable: {}", name),
+            LispError::Type { expected, got } => {
+                write!(f, "type mismatch: expected {}, got {}", expected, got)
+            }
+            LispError::Arity { expected, got } => {
+                write!(f, "wrong number of arguments: expected ")?;
+                match (*expected.start(), *expected.end()) {
+                    (min, usize::MAX) => write!(f, "at least {}", min)?,
+                    (min, max) if min == max => write!(f, "{}", min)?,
+                    (min, max) => write!(f, "{} to {}", min, max)?,
+                }
+                write!(f, ", got {}", got)
+            }
+            LispError::NotCallable(value) => write!(f, "not a procedure: {}", value),
+            LispError::Io(message) => write!(f, "I/O error: {}", message),
+            LispError::InvalidArgument(message) => write!(f, "{}", message),
+            LispError::Internal(message) => write!(f, "internal error: {}", message),
+            LispError::ReaderMacro(ch, error) => write!(f, "in reader macro #{}: {}", ch, error),
+            LispError::At(span, error) => write!(f, "{}: {}", span, error),
+        }
+    }
+}
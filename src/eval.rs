@@ -1,6 +1,10 @@
 use std::mem::size_of;
+use std::ops::RangeInclusive;
 
 use crate::env::Env;
+use crate::error::LispError;
+use crate::lexer::Span;
+use crate::symbol::Symbol;
 use crate::value::BuiltinFn;
 use crate::value::RefValue;
 use crate::value::Value;
@@ -19,20 +23,45 @@ pub struct VM {
     rr: Value,
     stack: Vec<StackData>,
     env: Env,
+    /// Source locations of the forms being evaluated, paired with the stack
+    /// index of their function slot.
+    forms: Vec<(usize, Span)>,
 }
 
 impl VM {
-    pub fn args(&self) -> impl Iterator<Item = Result<Value, String>> + '_ {
+    pub fn args(&self) -> impl Iterator<Item = Result<Value, LispError>> + '_ {
         self.stack[self.sp as usize + 1..].iter().map(|d| {
             if let StackData::Val(v) = d {
                 Ok(v.clone())
             } else {
-                Err("internal error".to_string())
+                Err(LispError::Internal("argument is not a value".to_string()))
             }
         })
     }
 
-    pub fn ret(&mut self, val: Value) -> Result<(), String> {
+    /// The number of arguments passed to the running builtin.
+    pub fn arg_count(&self) -> usize {
+        self.stack.len() - self.sp as usize - 1
+    }
+
+    /// The `index`th argument passed to the running builtin.
+    pub fn arg(&self, index: usize) -> Result<Value, LispError> {
+        self.args()
+            .nth(index)
+            .unwrap_or_else(|| Err(LispError::Internal("missing argument".to_string())))
+    }
+
+    /// Fail unless the running builtin was passed an allowed number of arguments.
+    pub fn check_arity(&self, expected: RangeInclusive<usize>) -> Result<(), LispError> {
+        let got = self.arg_count();
+        if expected.contains(&got) {
+            Ok(())
+        } else {
+            Err(LispError::Arity { expected, got })
+        }
+    }
+
+    pub fn ret(&mut self, val: Value) -> Result<(), LispError> {
         self.rr = val;
         self.stack.truncate(self.sp as usize); // clear args and fn
         self.sp -= 1;
@@ -57,11 +86,11 @@ impl VM {
         self.pp = value;
     }
 
-    pub fn pop_value(&mut self) -> Result<Value, String> {
+    pub fn pop_value(&mut self) -> Result<Value, LispError> {
         if let Some(StackData::Val(value)) = self.stack.pop() {
-            return Ok(value);
+            Ok(value)
         } else {
-            return Err("syntax error".to_string());
+            Err(LispError::Internal("stack top is not a value".to_string()))
         }
     }
 
@@ -77,7 +106,7 @@ impl VM {
         Value::Closure(args, body, self.env.clone())
     }
 
-    pub fn define(&self, ident: String, value: Value) {
+    pub fn define(&self, ident: Symbol, value: Value) {
         self.env.insert(ident, value);
     }
 
@@ -88,22 +117,44 @@ impl VM {
     pub fn print_env(&self) {
         self.env.print();
     }
+
+    /// Forget the forms whose function slot has been popped off the stack.
+    fn drop_finished_forms(&mut self) {
+        while let Some(&(index, _)) = self.forms.last() {
+            if index < self.stack.len() {
+                break;
+            }
+            self.forms.pop();
+        }
+    }
 }
 
-pub fn eval(val: Value, env: Env) -> Result<Value, String> {
+pub fn eval(val: Value, env: Env) -> Result<Value, LispError> {
     let mut vm = VM {
         pp: val,
         sp: 0i64,
         rr: Value::Null,
         stack: Vec::new(),
-        env: env,
+        env,
+        forms: Vec::new(),
     };
 
     log::debug!("size of StackData: {:?}", size_of::<StackData>());
     log::debug!("size of Value: {:?}", size_of::<Value>());
     log::debug!("size of Env: {:?}", size_of::<Env>());
 
+    run(&mut vm).map_err(|e| {
+        vm.drop_finished_forms();
+        match vm.forms.last() {
+            Some((_, span)) => e.at(span.clone()),
+            None => e,
+        }
+    })
+}
+
+fn run(vm: &mut VM) -> Result<Value, LispError> {
     loop {
+        vm.drop_finished_forms();
         log::debug!(
             "env:{:?}\tsp:{}\tpp:{:?}\trr:{:?}",
             vm.env,
@@ -115,6 +166,11 @@ pub fn eval(val: Value, env: Env) -> Result<Value, String> {
 
         match vm.pp.clone() {
             Value::Cons(car, cdr) => {
+                if vm.sp == vm.stack.len() as i64 {
+                    if let Some(span) = car.span() {
+                        vm.forms.push((vm.stack.len(), span));
+                    }
+                }
                 vm.stack.push(StackData::Frame {
                     next_sp: vm.sp,
                     next_pp: cdr.to_value(),
@@ -125,7 +181,10 @@ pub fn eval(val: Value, env: Env) -> Result<Value, String> {
             }
             Value::Null => {}
             Value::Ident(ident) => {
-                vm.rr = vm.env.get(ident.clone()).ok_or("unbound variable")?.clone();
+                vm.rr = match vm.env.get(ident) {
+                    Some(value) => value,
+                    None => return Err(LispError::Unbound { name: ident.to_string() }),
+                };
                 vm.pp = Value::Null;
                 vm.sp -= 1;
             }
@@ -142,21 +201,19 @@ pub fn eval(val: Value, env: Env) -> Result<Value, String> {
             continue;
         }
         if vm.sp < 0 {
-            return Ok(vm.rr);
+            return Ok(vm.rr.clone());
         }
 
         match vm.stack[vm.sp as usize].clone() {
             StackData::Val(Value::Closure(closure_args, closure_body, closure_env)) => {
                 let extended_env = closure_env.extend();
-                for (i, closure_arg) in closure_args.to_value().into_list_iter().enumerate() {
-                    let ident = closure_arg
+                let params = closure_args.to_value().into_list_iter().collect::<Vec<_>>();
+                vm.check_arity(params.len()..=params.len())?;
+                for (param, value) in params.into_iter().zip(vm.args()) {
+                    let ident = param
                         .try_into_ident()
-                        .or(Err("syntax error".to_string()))?;
-                    if let StackData::Val(value) = vm.stack[vm.sp as usize + 1 + i].clone() {
-                        extended_env.insert(ident, value);
-                    } else {
-                        return Err("internal error".to_string());
-                    }
+                        .or(Err(LispError::Syntax("lambda")))?;
+                    extended_env.insert(ident, value?);
                 }
                 vm.stack.truncate(vm.sp as usize);
                 vm.pp = closure_body.to_value();
@@ -171,20 +228,18 @@ pub fn eval(val: Value, env: Env) -> Result<Value, String> {
                 vm.sp += 1;
             }
             StackData::Val(Value::Subr(_name, f)) => {
-                f(&mut vm)?;
+                f(vm)?;
             }
             StackData::Val(Value::Cont(box_vm)) => {
-                if let Some(StackData::Val(arg)) = vm.stack.pop() {
-                    vm = *box_vm;
-                    vm.rr = arg;
-                    vm.stack.truncate(vm.sp as usize);
-                    vm.sp -= 1;
-                } else {
-                    return Err("internal error".to_string());
-                }
+                vm.check_arity(1..=1)?;
+                let arg = vm.pop_value()?;
+                *vm = *box_vm;
+                vm.rr = arg;
+                vm.stack.truncate(vm.sp as usize);
+                vm.sp -= 1;
             }
-            StackData::Val(_) => {
-                return Err("invalid application".to_string());
+            StackData::Val(value) => {
+                return Err(LispError::NotCallable(format!("{:?}", value)));
             }
             StackData::Frame { next_sp, next_pp } => {
                 vm.pp = next_pp;
@@ -192,7 +247,7 @@ pub fn eval(val: Value, env: Env) -> Result<Value, String> {
                 vm.stack.pop();
                 vm.stack.push(StackData::Val(vm.rr.clone()));
                 if let StackData::Val(Value::Syntax(_name, f)) = vm.stack[vm.sp as usize].clone() {
-                    f(&mut vm)?;
+                    f(vm)?;
                 }
             }
             StackData::Env(e) => {
@@ -1,81 +1,693 @@
+use crate::error::LispError;
+use crate::number::{looks_numeric, parse_number, Number};
+use crate::parser::is_dispatch_macro_defined;
+use crate::printer::{Sharing, Written};
+use crate::symbol::Symbol;
+use crate::value::Value;
+
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fmt;
 use std::iter::Peekable;
+use std::marker::PhantomData;
+use std::rc::Rc;
 
 /// Lexical token
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone)]
 pub enum Token {
     LPER,
     RPER,
+    LVECTOR,
+    LBYTEVECTOR,
     LBRACE,
     RBRACE,
+    /// `(` written directly after a datum, as in `f(x)`
+    NEOLPER,
+    /// `{` written directly after a datum, as in `f{x}`
+    NEOLBRACE,
     QUOTE,
+    QUASIQUOTE,
+    UNQUOTE,
+    UNQUOTESPLICING,
     DOT,
     BOOL(bool),
-    IDENT(String),
-    NUM(f64),
+    IDENT(Symbol),
+    NUM(Number),
+    STRING(String),
+    CHAR(char),
+    DATUMCOMMENT,
+    /// `#n=`, labelling the datum that follows
+    LABELDEF(usize),
+    /// `#n#`, referring to the datum labelled `n`
+    LABELREF(usize),
+    /// `#` followed by a character with no built-in meaning, which a reader
+    /// macro may define
+    DISPATCH(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::LPER | Token::NEOLPER => write!(f, "'('"),
+            Token::RPER => write!(f, "')'"),
+            Token::LVECTOR => write!(f, "'#('"),
+            Token::LBYTEVECTOR => write!(f, "'#u8('"),
+            Token::LBRACE | Token::NEOLBRACE => write!(f, "'{{'"),
+            Token::RBRACE => write!(f, "'}}'"),
+            Token::QUOTE => write!(f, "'''"),
+            Token::QUASIQUOTE => write!(f, "'`'"),
+            Token::UNQUOTE => write!(f, "','"),
+            Token::UNQUOTESPLICING => write!(f, "',@'"),
+            Token::DOT => write!(f, "'.'"),
+            Token::DATUMCOMMENT => write!(f, "'#;'"),
+            Token::LABELDEF(n) => write!(f, "'#{}='", n),
+            Token::LABELREF(n) => write!(f, "'#{}#'", n),
+            Token::DISPATCH(ch) => write!(f, "'#{}'", ch),
+            Token::BOOL(b) => write_atom(Value::Bool(*b), f),
+            Token::IDENT(ident) => write_atom(Value::Ident(*ident), f),
+            Token::NUM(num) => write_atom(Value::from(num.clone()), f),
+            Token::STRING(string) => write_atom(Value::Str(string.clone()), f),
+            Token::CHAR(ch) => write_atom(Value::Char(*ch), f),
+        }
+    }
+}
+
+/// Write an atom as it would appear in source, for error messages.
+fn write_atom(atom: Value, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", Written::new(&atom, Sharing::Cycles))
+}
+
+/// Character names accepted after `#\`, in the order the printer prefers them.
+pub static CHAR_NAMES: &[(&str, char)] = &[
+    ("alarm", '\u{7}'),
+    ("backspace", '\u{8}'),
+    ("delete", '\u{7f}'),
+    ("escape", '\u{1b}'),
+    ("newline", '\n'),
+    ("null", '\0'),
+    ("return", '\r'),
+    ("space", ' '),
+    ("tab", '\t'),
+];
+
+/// A location in source code. Lines and columns start from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Characters for the lexer to read. A source that holds all of its input
+/// can hand out runs of it without copying.
+pub trait Source<'a> {
+    fn next_char(&mut self) -> Option<char>;
+    fn peek_char(&mut self) -> Option<char>;
+    /// Consume characters up to the first for which `pred` is false.
+    fn take_while(&mut self, pred: fn(char) -> bool) -> Cow<'a, str>;
+}
+
+impl<'a, C: Iterator<Item = char>> Source<'a> for Peekable<C> {
+    fn next_char(&mut self) -> Option<char> {
+        self.next()
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.peek().copied()
+    }
+
+    fn take_while(&mut self, pred: fn(char) -> bool) -> Cow<'a, str> {
+        let mut text = String::new();
+        while let Some(ch) = self.next_if(|&ch| pred(ch)) {
+            text.push(ch);
+        }
+        Cow::Owned(text)
+    }
+}
+
+/// A source reading a string in memory, which hands out slices of it.
+pub struct StrSource<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Source<'a> for StrSource<'a> {
+    fn next_char(&mut self) -> Option<char> {
+        let ch = self.peek_char()?;
+        self.rest = &self.rest[ch.len_utf8()..];
+        Some(ch)
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn take_while(&mut self, pred: fn(char) -> bool) -> Cow<'a, str> {
+        let bytes = self.rest.as_bytes();
+        let mut end = 0;
+        while end < bytes.len() {
+            // decode only the non-ASCII characters
+            let ch = match bytes[end] {
+                byte if byte.is_ascii() => byte as char,
+                _ => self.rest[end..].chars().next().unwrap(),
+            };
+            if !pred(ch) {
+                break;
+            }
+            end += ch.len_utf8();
+        }
+        let (taken, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Cow::Borrowed(taken)
+    }
 }
 
 /// Lisp lexer
-pub struct Lexer<C: Iterator<Item = char>> {
-    reader: Peekable<C>,
+pub struct Lexer<'a, S: Source<'a>> {
+    reader: S,
+    file: Rc<str>,
+    line: usize,
+    column: usize,
+    start: Span,
+    /// Where the last token ended, if it can be followed by a neoteric suffix.
+    datum_end: Option<(usize, usize)>,
+    /// Whether a neoteric suffix follows the last token: it ended a datum and
+    /// the next character is `(` or `{`. Shared so that a parser can tell
+    /// without reading the next token, which may be on a line not typed yet.
+    suffix_follows: Rc<Cell<bool>>,
+    /// Whether identifiers and character names are folded to lower case, as
+    /// set by the `#!fold-case` and `#!no-fold-case` directives.
+    fold_case: bool,
+    /// The text consumed since the last `take_text`, when recording for a
+    /// concrete syntax tree.
+    recorded: Option<String>,
+    /// The length of `recorded` where the current token starts.
+    token_offset: usize,
+    source: PhantomData<&'a str>,
 }
-impl<C: Iterator<Item = char>> Lexer<C> {
-    /// Create a new lexer that consumes `reader`.
-    pub fn new(reader: C) -> Lexer<C> {
+impl<C: Iterator<Item = char>> Lexer<'static, Peekable<C>> {
+    /// Create a new lexer that consumes `reader`, naming `file` in its spans.
+    pub fn new(reader: C, file: &str) -> Self {
+        Lexer::with_source(reader.peekable(), file)
+    }
+}
+impl<'a> Lexer<'a, StrSource<'a>> {
+    /// Create a lexer that reads `text` without copying identifiers out of it.
+    pub fn new_str(text: &'a str, file: &str) -> Self {
+        Lexer::with_source(StrSource { rest: text }, file)
+    }
+
+    /// Create a lexer that reads `bytes`, which must be UTF-8, like `new_str`.
+    pub fn new_bytes(bytes: &'a [u8], file: &str) -> Result<Self, LispError> {
+        let text = std::str::from_utf8(bytes).map_err(|e| LispError::Io(e.to_string()))?;
+        Ok(Lexer::new_str(text, file))
+    }
+}
+impl<'a, S: Source<'a>> Lexer<'a, S> {
+    fn with_source(reader: S, file: &str) -> Self {
+        let file: Rc<str> = Rc::from(file);
         Lexer {
-            reader: reader.peekable(),
+            reader,
+            start: Span {
+                file: file.clone(),
+                line: 1,
+                column: 1,
+            },
+            file,
+            line: 1,
+            column: 1,
+            datum_end: None,
+            suffix_follows: Rc::new(Cell::new(false)),
+            fold_case: false,
+            recorded: None,
+            token_offset: 0,
+            source: PhantomData,
         }
     }
-}
-impl<C: Iterator<Item = char>> Iterator for Lexer<C> {
-    type Item = Result<Token, String>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut buf = String::new();
-        while let Some(ch) = self.reader.next() {
-            if is_identifier_char(ch) {
-                buf.push(ch);
+    /// A flag that is set while a neoteric suffix follows the last token.
+    pub fn suffix_follows(&self) -> Rc<Cell<bool>> {
+        self.suffix_follows.clone()
+    }
+
+    /// Make this lexer record the text it consumes, for `take_text`.
+    pub fn lossless(mut self) -> Self {
+        self.recorded = Some(String::new());
+        self
+    }
+
+    /// Take the text consumed since the last call, split into the whitespace
+    /// and comments before the last token and the token itself. Called after
+    /// `next` returns `None`, the first part is the trivia at the end of the
+    /// input. Both parts are empty unless the lexer is `lossless`.
+    pub fn take_text(&mut self) -> (String, String) {
+        let recorded = match &mut self.recorded {
+            Some(recorded) => recorded,
+            None => return (String::new(), String::new()),
+        };
+        let text = recorded.split_off(self.token_offset);
+        self.token_offset = 0;
+        (std::mem::take(recorded), text)
+    }
+
+    /// The location of the next character.
+    fn here(&self) -> Span {
+        Span {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// Consume one character, keeping track of the current position.
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.reader.next_char()?;
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(ch);
+        }
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(ch)
+    }
+
+    /// Consume characters up to the first for which `pred` is false.
+    fn bump_while(&mut self, pred: fn(char) -> bool) -> Cow<'a, str> {
+        let text = self.reader.take_while(pred);
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push_str(&text);
+        }
+        for ch in text.chars() {
+            if ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
             }
-            let peek = self.reader.peek().cloned();
+        }
+        text
+    }
+
+    /// Consume the next character if it is `expected`.
+    fn eat(&mut self, expected: char) -> bool {
+        if self.reader.peek_char() == Some(expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Read the next token, leaving its starting position in `self.start`.
+    fn read_token(&mut self) -> Option<Result<Token, LispError>> {
+        loop {
+            self.bump_while(char::is_whitespace);
+            self.start = self.here();
+            self.token_offset = self.recorded.as_ref().map_or(0, String::len);
+            if self.reader.peek_char().is_some_and(is_identifier_char) {
+                return Some(self.read_identifier());
+            }
+            let ch = self.bump()?;
+            let peek = self.reader.peek_char();
             let token = match (ch, peek) {
                 ('(', _) => Token::LPER,
                 (')', _) => Token::RPER,
                 ('{', _) => Token::LBRACE,
                 ('}', _) => Token::RBRACE,
                 ('\'', _) => Token::QUOTE,
-                ('.', None) => Token::DOT,
-                ('.', Some(peek)) if !is_identifier_char(peek) => Token::DOT,
-                ('#', Some(_)) => match self.reader.next().unwrap() {
-                    't' => Token::BOOL(true),
-                    'f' => Token::BOOL(false),
-                    _ => return Some(Err("lexer error".to_string())),
+                ('`', _) => Token::QUASIQUOTE,
+                (',', Some('@')) => {
+                    self.bump();
+                    Token::UNQUOTESPLICING
+                }
+                (',', _) => Token::UNQUOTE,
+                (';', _) => {
+                    self.bump_while(|ch| ch != '\n');
+                    self.bump();
+                    continue;
+                }
+                ('"', _) => match self.read_delimited('"') {
+                    Ok(string) => Token::STRING(string),
+                    Err(e) => return Some(Err(e)),
+                },
+                ('|', _) => match self.read_delimited('|') {
+                    Ok(ident) => Token::IDENT(Symbol::intern(&ident)),
+                    Err(e) => return Some(Err(e)),
                 },
-                (_, _) if is_identifier_char(ch) => {
-                    if let Some(peek) = peek {
-                        if is_identifier_char(peek) {
-                            continue;
+                // a delimiter after `#` is left to be read again, so that an
+                // error here does not swallow the `)` that closes a list
+                ('#', Some(next)) if next.is_whitespace() || ")}\"".contains(next) => {
+                    return Some(Err(LispError::Lex("unknown # syntax".to_string())))
+                }
+                ('#', Some(_)) => match self.bump().unwrap() {
+                    '(' => Token::LVECTOR,
+                    'u' if self.eat('8') => match self.reader.peek_char() {
+                        Some('(') => {
+                            self.bump();
+                            Token::LBYTEVECTOR
+                        }
+                        Some(_) => {
+                            return Some(Err(LispError::Lex("expected ( after #u8".to_string())))
+                        }
+                        None => {
+                            return Some(Err(LispError::Incomplete(
+                                "unexpected end of input".to_string(),
+                            )))
+                        }
+                    },
+                    ch @ 't' | ch @ 'f' => match (ch, &*self.bump_while(is_identifier_char)) {
+                        ('t', "") | ('t', "rue") => Token::BOOL(true),
+                        ('f', "") | ('f', "alse") => Token::BOOL(false),
+                        _ => return Some(Err(LispError::Lex("unknown # syntax".to_string()))),
+                    },
+                    '|' => match self.skip_block_comment() {
+                        Ok(()) => continue,
+                        Err(e) => return Some(Err(e)),
+                    },
+                    ';' => Token::DATUMCOMMENT,
+                    '!' => match self.read_directive() {
+                        Ok(()) => continue,
+                        Err(e) => return Some(Err(e)),
+                    },
+                    '\\' => match self.read_char() {
+                        Ok(ch) => Token::CHAR(ch),
+                        Err(e) => return Some(Err(e)),
+                    },
+                    digit if digit.is_ascii_digit() => match self.read_label(digit) {
+                        Ok(token) => token,
+                        Err(e) => return Some(Err(e)),
+                    },
+                    prefix if "bodxeiBODXEI".contains(prefix) => {
+                        let rest = self.bump_while(|ch| is_identifier_char(ch) || ch == '#');
+                        let text = format!("#{}{}", prefix, rest);
+                        match parse_number(&text) {
+                            Some(num) => Token::NUM(num),
+                            None => return Some(Err(malformed_number(&text))),
                         }
                     }
-                    if let Ok(num) = buf.parse() {
-                        Token::NUM(num)
-                    } else {
-                        Token::IDENT(buf)
+                    ch if is_identifier_char(ch) && is_dispatch_macro_defined(ch) => {
+                        Token::DISPATCH(ch)
                     }
+                    // the rest of the token is skipped with the error, rather
+                    // than read as a datum of its own
+                    ch if is_identifier_char(ch) => {
+                        return Some(Err(LispError::Lex(format!("unknown # syntax #{}", ch))))
+                    }
+                    _ => return Some(Err(LispError::Lex("unknown # syntax".to_string()))),
+                },
+                ('#', None) => {
+                    return Some(Err(LispError::Incomplete(
+                        "unexpected end of input".to_string(),
+                    )))
                 }
                 _ => continue,
             };
             return Some(Ok(token));
         }
-        None
     }
+
+    /// Read an identifier, a number, or the `.` of a dotted pair.
+    fn read_identifier(&mut self) -> Result<Token, LispError> {
+        let text = self.bump_while(is_identifier_char);
+        if text == "." {
+            return Ok(Token::DOT);
+        }
+        match parse_number(&text) {
+            Some(num) => Ok(Token::NUM(num)),
+            None if looks_numeric(&text) => Err(malformed_number(&text)),
+            None if self.fold_case => Ok(Token::IDENT(Symbol::intern(&text.to_lowercase()))),
+            None => Ok(Token::IDENT(Symbol::intern(&text))),
+        }
+    }
+
+    /// Read the rest of a string literal or `|identifier|` up to the closing
+    /// `delimiter`, handling escapes.
+    fn read_delimited(&mut self, delimiter: char) -> Result<String, LispError> {
+        let mut string = String::new();
+        // a bad escape is reported once the closing delimiter is found, so
+        // that lexing resumes after the string rather than inside it
+        let mut error = None;
+        loop {
+            match self.bump() {
+                Some(ch) if ch == delimiter => return error.map_or(Ok(string), Err),
+                Some('\\') => match self.bump() {
+                    Some('a') => string.push('\u{7}'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('t') => string.push('\t'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('|') => string.push('|'),
+                    Some('x') | Some('X') => match self.read_hex_escape() {
+                        Ok(ch) => string.push(ch),
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    },
+                    Some(ch) if is_intraline_whitespace(ch) || ch == '\n' || ch == '\r' => {
+                        if let Err(e) = self.skip_line_continuation(ch) {
+                            error.get_or_insert(e);
+                        }
+                    }
+                    // let the outer match report the end of input
+                    None => continue,
+                    _ => {
+                        error.get_or_insert(LispError::Lex("invalid escape".to_string()));
+                    }
+                },
+                Some(ch) => string.push(ch),
+                None if delimiter == '"' => {
+                    return Err(LispError::Incomplete("unterminated string".to_string()))
+                }
+                None => return Err(LispError::Incomplete("unterminated identifier".to_string())),
+            }
+        }
+    }
+
+    /// Read the rest of a `#!` directive. At the very start of the input,
+    /// `#!/` or `#! ` begins a shebang line, which is skipped.
+    fn read_directive(&mut self) -> Result<(), LispError> {
+        let at_start = self.start.line == 1 && self.start.column == 1;
+        if at_start && matches!(self.reader.peek_char(), Some('/') | Some(' ')) {
+            self.bump_while(|ch| ch != '\n');
+            self.bump();
+            return Ok(());
+        }
+        let name = self.bump_while(is_identifier_char);
+        match &*name {
+            "fold-case" => self.fold_case = true,
+            "no-fold-case" => self.fold_case = false,
+            _ => return Err(LispError::Lex(format!("unknown directive #!{}", name))),
+        }
+        Ok(())
+    }
+
+    /// Read the rest of a datum label `#n=` or `#n#` whose first digit was `first`.
+    fn read_label(&mut self, first: char) -> Result<Token, LispError> {
+        let mut digits = first.to_string();
+        loop {
+            match self.bump() {
+                Some(ch) if ch.is_ascii_digit() => digits.push(ch),
+                Some(end @ '=') | Some(end @ '#') => {
+                    let n = digits
+                        .parse()
+                        .map_err(|_| LispError::Lex("datum label too large".to_string()))?;
+                    return Ok(if end == '=' {
+                        Token::LABELDEF(n)
+                    } else {
+                        Token::LABELREF(n)
+                    });
+                }
+                Some(_) => return Err(LispError::Lex("malformed datum label".to_string())),
+                None => return Err(LispError::Incomplete("unexpected end of input".to_string())),
+            }
+        }
+    }
+
+    /// Read `<hex digits>;` after `\x` and return the character it denotes.
+    fn read_hex_escape(&mut self) -> Result<char, LispError> {
+        let mut hex = String::new();
+        // stop short of anything else, which may be the closing delimiter
+        loop {
+            match self.reader.peek_char() {
+                Some(';') => break,
+                Some(ch) if ch.is_ascii_hexdigit() => hex.push(ch),
+                _ => return Err(LispError::Lex("invalid hex escape".to_string())),
+            }
+            self.bump();
+        }
+        self.bump();
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .ok_or_else(|| LispError::Lex("invalid hex escape".to_string()))
+    }
+
+    /// Read the rest of a character literal after `#\`.
+    fn read_char(&mut self) -> Result<char, LispError> {
+        let first = self
+            .bump()
+            .ok_or_else(|| LispError::Incomplete("unexpected end of input".to_string()))?;
+        let mut name = first.to_string();
+        name.push_str(&self.bump_while(is_identifier_char));
+        if name.chars().count() == 1 {
+            return Ok(first);
+        }
+        if self.fold_case {
+            name = name.to_lowercase();
+        }
+        if let Some(&(_, ch)) = CHAR_NAMES.iter().find(|&&(n, _)| n == name) {
+            return Ok(ch);
+        }
+        if first == 'x' || first == 'X' {
+            if let Some(ch) = u32::from_str_radix(&name[1..], 16)
+                .ok()
+                .and_then(std::char::from_u32)
+            {
+                return Ok(ch);
+            }
+        }
+        Err(LispError::Lex(format!(
+            "unknown character name #\\{}",
+            name
+        )))
+    }
+
+    /// Skip the rest of a (possibly nested) block comment after `#|`.
+    fn skip_block_comment(&mut self) -> Result<(), LispError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.bump(), self.reader.peek_char()) {
+                (Some('|'), Some('#')) => depth -= 1,
+                (Some('#'), Some('|')) => depth += 1,
+                (Some(_), _) => continue,
+                (None, _) => {
+                    return Err(LispError::Incomplete(
+                        "unterminated block comment".to_string(),
+                    ))
+                }
+            }
+            self.bump();
+        }
+        Ok(())
+    }
+
+    /// Skip the rest of a malformed token, up to the next whitespace,
+    /// bracket, `"`, `;` or `|`.
+    fn skip_to_delimiter(&mut self) {
+        self.bump_while(|ch| !ch.is_whitespace() && !"()[]{}\";|".contains(ch));
+    }
+
+    /// Skip `\<intraline whitespace>*<line ending><intraline whitespace>*`.
+    /// `first` is the character that followed the backslash. A line ending is
+    /// `\n`, `\r\n` or `\r`.
+    fn skip_line_continuation(&mut self, first: char) -> Result<(), LispError> {
+        let mut seen_newline = first == '\n' || first == '\r';
+        if first == '\r' {
+            self.eat('\n');
+        }
+        while let Some(ch) = self.reader.peek_char() {
+            if (ch == '\n' || ch == '\r') && !seen_newline {
+                seen_newline = true;
+                self.bump();
+                if ch == '\r' {
+                    self.eat('\n');
+                }
+            } else if is_intraline_whitespace(ch) {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        if seen_newline {
+            Ok(())
+        } else {
+            Err(LispError::Lex("invalid escape".to_string()))
+        }
+    }
+}
+impl<'a, S: Source<'a>> Iterator for Lexer<'a, S> {
+    type Item = Result<(Token, Span), LispError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = match self.read_token()? {
+            Ok(token) => token,
+            Err(e) => {
+                if let LispError::Lex(_) = e {
+                    self.skip_to_delimiter();
+                }
+                return Some(Err(e.at(self.start.clone())));
+            }
+        };
+        let adjacent = self.datum_end == Some((self.start.line, self.start.column));
+        let token = match token {
+            Token::LPER if adjacent => Token::NEOLPER,
+            Token::LBRACE if adjacent => Token::NEOLBRACE,
+            token => token,
+        };
+        self.datum_end = match token {
+            Token::RPER
+            | Token::RBRACE
+            | Token::BOOL(_)
+            | Token::IDENT(_)
+            | Token::NUM(_)
+            | Token::STRING(_)
+            | Token::CHAR(_)
+            | Token::LABELREF(_) => Some((self.line, self.column)),
+            _ => None,
+        };
+        let suffix =
+            self.datum_end.is_some() && matches!(self.reader.peek_char(), Some('(') | Some('{'));
+        self.suffix_follows.set(suffix);
+        Some(Ok((token, self.start.clone())))
+    }
+}
+
+/// Whether a reader macro can be defined for `#ch`: `ch` must not already
+/// have a meaning built into the lexer.
+pub fn is_dispatch_macro_char(ch: char) -> bool {
+    let builtin = "(tfu|;!\\".contains(ch) || ch.is_ascii_digit() || "bodxeiBODXEI".contains(ch);
+    is_identifier_char(ch) && !builtin
+}
+
+fn malformed_number(text: &str) -> LispError {
+    LispError::Lex(format!("malformed number {}", text))
+}
+
+/// return true if `ch` is a space or a tab.
+fn is_intraline_whitespace(ch: char) -> bool {
+    ch == ' ' || ch == '\t'
+}
+
+/// return true if `name` can't be written as a plain identifier and has to be
+/// written as `|name|` instead.
+pub fn needs_bars(name: &str) -> bool {
+    name.is_empty()
+        || name == "."
+        || looks_numeric(name)
+        || parse_number(name).is_some()
+        || !name.chars().all(is_identifier_char)
 }
 
-/// return true if `ch` is one of extended identifier characters:
+/// return true if `ch` is a letter, one of extended identifier characters:
 /// ```! $ % & * + - . / : < = > ? @ ^ _ ~```
+/// or any other non-ASCII character that isn't whitespace.
 #[rustfmt::skip]
 fn is_identifier_char(ch: char) -> bool {
     ch.is_ascii_alphabetic() ||
-    ('-' <= ch && ch <= ':') ||
-    ('<' <= ch && ch <= '@') ||
+    (!ch.is_ascii() && !ch.is_whitespace() && !ch.is_control()) ||
+    ('-'..=':').contains(&ch) ||
+    ('<'..='@').contains(&ch) ||
     ch == '_' ||
     ch == '*' ||
     ch == '+' ||
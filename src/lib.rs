@@ -0,0 +1,16 @@
+mod builtins;
+pub mod cst;
+pub mod env;
+pub mod error;
+pub mod eval;
+pub mod lexer;
+mod number;
+mod parser;
+pub mod printer;
+pub mod reader;
+mod symbol;
+pub mod value;
+
+pub use number::Number;
+pub use parser::set_dispatch_macro;
+pub use symbol::Symbol;
@@ -1,18 +1,17 @@
-mod builtins;
-mod env;
-mod eval;
-mod lexer;
-mod parser;
-mod value;
-
-use crate::env::Env;
-use crate::eval::eval;
-use crate::lexer::Lexer;
-use crate::parser::parse;
-
-use std::fs::File;
-use std::io::{stdin, stdout, BufRead, BufReader, Write};
-use std::path::PathBuf;
+use rust_lisp::cst::Cst;
+use rust_lisp::env::Env;
+use rust_lisp::error::LispError;
+use rust_lisp::eval::eval;
+use rust_lisp::lexer::Source;
+use rust_lisp::printer::{Sharing, Written};
+use rust_lisp::reader::Reader;
+use rust_lisp::value::Value;
+
+use std::cell::Cell;
+use std::fs;
+use std::io::{self, stdin, stdout, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use structopt::*;
 
@@ -22,6 +21,10 @@ struct Opt {
     #[structopt(short = "d", long = "debug")]
     debug: bool,
 
+    /// Print FILE back from its concrete syntax tree instead of running it
+    #[structopt(long = "reprint", requires = "FILE")]
+    reprint: bool,
+
     /// Script file to run
     #[structopt(name = "FILE", parse(from_os_str))]
     file: Option<PathBuf>,
@@ -46,77 +49,139 @@ fn main() {
     }
     builder.init();
 
-    let input: Box<dyn Iterator<Item = char>> = {
-        if let Some(path) = &opt.file {
-            let file = File::open(path).unwrap();
-            Box::new(buf_reader_to_chars(BufReader::new(file)))
-        } else {
-            Box::new(StdinIter::new())
+    if opt.reprint {
+        let path = opt.file.as_ref().unwrap();
+        match fs::read_to_string(path) {
+            Ok(source) => print!("{}", Cst::read(source.chars(), &path.display().to_string())),
+            Err(e) => log::error!("{}: {}", path.display(), e),
         }
-    };
+        return;
+    }
 
-    let mut lexer = Lexer::new(input).peekable();
     let env = Env::new_default();
+    match &opt.file {
+        Some(path) => run_file(path, env),
+        None => run_repl(env),
+    }
+}
 
-    loop {
-        if opt.file.is_none() {
-            print!("> ");
-            stdout().flush().unwrap();
+/// Evaluate every form in the script at `path`. Evaluation stops at the first
+/// syntax error, but the rest of the file is still read so that all of its
+/// syntax errors are reported together.
+fn run_file(path: &Path, env: Env) {
+    let source = match fs::read(path) {
+        Ok(source) => source,
+        Err(e) => {
+            log::error!("{}: {}", path.display(), e);
+            return;
         }
-
-        if let None = lexer.peek() {
-            break;
+    };
+    let mut reader = match Reader::new_bytes(&source, &path.display().to_string()) {
+        Ok(reader) => reader,
+        Err(e) => {
+            log::error!("{}: {}", path.display(), e);
+            return;
         }
-        let parsed = match parse(&mut lexer) {
-            Ok(v) => v,
-            Err(e) => {
-                log::error!("{}", e);
-                break;
+    };
+    let mut syntax_errors = Vec::new();
+    while let Some(datum) = reader.read_one() {
+        match datum {
+            Ok(parsed) if syntax_errors.is_empty() => {
+                if let Err(e) = eval_read(parsed, &reader, &env) {
+                    log::error!("{}", e);
+                }
             }
+            Ok(_) => {}
+            Err(e) => syntax_errors.push(e),
+        }
+    }
+    for e in &syntax_errors {
+        log::error!("{}", e);
+    }
+    if !syntax_errors.is_empty() {
+        log::error!(
+            "{}: {} syntax error(s); forms after the first were not evaluated",
+            path.display(),
+            syntax_errors.len()
+        );
+    }
+}
+
+/// Read forms from stdin and print their values. The reader keeps its
+/// state from one line to the next, so a form can span lines, prompted with
+/// `... `, and is read only once.
+fn run_repl(env: Env) {
+    let prompt = Rc::new(Cell::new("> "));
+    let input = Prompter {
+        input: stdin().lock(),
+        prompt: prompt.clone(),
+        at_line_start: true,
+    };
+    let mut reader = Reader::from_buf_read(input, "<stdin>");
+    loop {
+        prompt.set("> ");
+        reader.skip_atmosphere();
+        prompt.set("... ");
+        let datum = match reader.read_one() {
+            Some(datum) => datum,
+            None => break,
         };
-        match eval(parsed, env.clone()) {
+        match datum.and_then(|parsed| eval_read(parsed, &reader, &env)) {
             Ok(value) => {
-                if opt.file.is_none() {
-                    println!("{:?}", value);
-                    println!("");
-                }
+                println!("{}", Written::new(&value, Sharing::Cycles));
+                println!();
             }
             Err(e) => {
-                log::error!("{:?}", e);
+                log::error!("{}", e);
             }
         }
     }
 }
 
-fn buf_reader_to_chars(buf_reader: impl BufRead) -> impl Iterator<Item = char> {
-    buf_reader
-        .lines()
-        .map(|s| -> Vec<char> { s.unwrap().chars().collect() })
-        .flatten()
+/// Evaluate `datum`, which `reader` has just read. An error that has no
+/// location of its own, like one in an atom or in code a builtin generated,
+/// is located at the start of `datum`.
+fn eval_read<'a, S: Source<'a>>(
+    datum: Value,
+    reader: &Reader<'a, S>,
+    env: &Env,
+) -> Result<Value, LispError> {
+    eval(datum, env.clone()).map_err(|e| match reader.datum_span() {
+        Some(span) => e.at(span.clone()),
+        None => e,
+    })
 }
 
-struct StdinIter {
-    buf: Vec<char>,
+/// Input that prints `prompt` before each line is read from it.
+struct Prompter<R> {
+    input: R,
+    prompt: Rc<Cell<&'static str>>,
+    at_line_start: bool,
 }
-impl StdinIter {
-    fn new() -> StdinIter {
-        StdinIter { buf: Vec::new() }
+
+impl<R: BufRead> Read for Prompter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
     }
 }
-impl Iterator for StdinIter {
-    type Item = char;
-
-    fn next(&mut self) -> Option<char> {
-        if self.buf.is_empty() {
-            let mut buf = String::new();
-            let bytes = stdin().read_line(&mut buf).unwrap();
-            if bytes == 0 {
-                return None;
-            }
-            self.buf = buf.chars().collect();
+
+impl<R: BufRead> BufRead for Prompter<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.at_line_start {
+            print!("{}", self.prompt.get());
+            stdout().flush()?;
+            self.at_line_start = false;
+        }
+        self.input.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let buffer = self.input.fill_buf().unwrap_or(&[]);
+        if buffer[..amt.min(buffer.len())].contains(&b'\n') {
+            self.at_line_start = true;
         }
-        let mut buf = self.buf.split_off(1);
-        std::mem::swap(&mut buf, &mut self.buf);
-        Some(buf[0])
+        self.input.consume(amt);
     }
 }
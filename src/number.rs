@@ -0,0 +1,611 @@
+//! Numeric literal syntax, following the `<number>` grammar of R7RS section 7.1.1
+//! without complex numbers.
+
+use crate::error::LispError;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::rc::Rc;
+
+/// A number: an exact integer or rational, or an inexact real. Exact
+/// numbers are always normalized: integers that fit in an `i64` are `Int`,
+/// larger ones `Big`, and `Rat` is in lowest terms with a denominator above 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Big(Rc<BigInt>),
+    Rat(Rc<BigRational>),
+    Real(f64),
+}
+
+impl Number {
+    /// The exact integer `n`, as `Int` if it fits.
+    pub fn from_big(n: BigInt) -> Number {
+        match n.to_i64() {
+            Some(n) => Number::Int(n),
+            None => Number::Big(Rc::new(n)),
+        }
+    }
+
+    /// The exact number `r`, as an integer if it is one.
+    pub fn from_ratio(r: BigRational) -> Number {
+        if r.is_integer() {
+            Number::from_big(r.to_integer())
+        } else {
+            Number::Rat(Rc::new(r))
+        }
+    }
+
+    fn to_big(&self) -> Option<BigInt> {
+        match self {
+            Number::Int(n) => Some(BigInt::from(*n)),
+            Number::Big(n) => Some((**n).clone()),
+            Number::Rat(_) | Number::Real(_) => None,
+        }
+    }
+
+    fn to_ratio(&self) -> Option<BigRational> {
+        match self {
+            Number::Rat(r) => Some((**r).clone()),
+            Number::Real(_) => None,
+            integer => integer.to_big().map(BigRational::from_integer),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Int(n) => *n as f64,
+            Number::Big(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Rat(r) => r.to_f64().unwrap_or(f64::NAN),
+            Number::Real(x) => *x,
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        !matches!(self, Number::Real(_))
+    }
+
+    /// The exact number equal to this one.
+    pub fn into_exact(self) -> Result<Number, LispError> {
+        match self {
+            Number::Real(x) => match BigRational::from_float(x) {
+                Some(r) => Ok(Number::from_ratio(r)),
+                None => Err(LispError::InvalidArgument(format!(
+                    "no exact representation of {}",
+                    Number::Real(x)
+                ))),
+            },
+            exact => Ok(exact),
+        }
+    }
+
+    /// The inexact number closest to this one.
+    pub fn into_inexact(self) -> Number {
+        Number::Real(self.to_f64())
+    }
+
+    /// Combine two numbers with `exact` if both are exact, or with `inexact`
+    /// on their inexact values otherwise, as R7RS's contagion rule says.
+    /// Two `Int`s are combined with `small` unless it overflows.
+    fn combine(
+        self,
+        other: Number,
+        small: fn(i64, i64) -> Option<i64>,
+        exact: fn(BigRational, BigRational) -> BigRational,
+        inexact: fn(f64, f64) -> f64,
+    ) -> Number {
+        if let (Number::Int(a), Number::Int(b)) = (&self, &other) {
+            if let Some(n) = small(*a, *b) {
+                return Number::Int(n);
+            }
+        }
+        match (self.to_ratio(), other.to_ratio()) {
+            (Some(a), Some(b)) => Number::from_ratio(exact(a, b)),
+            _ => Number::Real(inexact(self.to_f64(), other.to_f64())),
+        }
+    }
+
+    /// Divide, giving an exact quotient when both are exact. Unlike the other
+    /// arithmetic operations this can fail, so it is not `Div`.
+    pub fn try_div(self, other: Number) -> Result<Number, LispError> {
+        if other.is_exact() && other.is_zero() && self.is_exact() {
+            return Err(division_by_zero());
+        }
+        let exact_div = |a: i64, b: i64| match a.checked_rem(b) {
+            Some(0) => a.checked_div(b),
+            _ => None,
+        };
+        Ok(self.combine(other, exact_div, |a, b| a / b, |a, b| a / b))
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Number::Int(n) => *n == 0,
+            Number::Big(_) | Number::Rat(_) => false,
+            Number::Real(x) => *x == 0.0,
+        }
+    }
+
+    /// Combine two integers with an integer division operation: `small` on
+    /// two `Int`s unless it overflows, `big` on exact integers, and `inexact`
+    /// otherwise. Fails if either isn't an integer or `other` is zero.
+    fn divide_integers(
+        self,
+        other: Number,
+        small: fn(i64, i64) -> Option<i64>,
+        big: fn(BigInt, BigInt) -> BigInt,
+        inexact: fn(f64, f64) -> f64,
+    ) -> Result<Number, LispError> {
+        self.check_integer()?;
+        other.check_integer()?;
+        if other.is_zero() {
+            return Err(division_by_zero());
+        }
+        Ok(integer_op(self, other, small, big, inexact))
+    }
+
+    /// The quotient of integer division, rounded toward zero.
+    pub fn quotient(self, other: Number) -> Result<Number, LispError> {
+        self.divide_integers(other, i64::checked_div, |a, b| a / b, |a, b| {
+            (a / b).trunc()
+        })
+    }
+
+    /// The remainder of integer division, with the sign of `self`.
+    pub fn remainder(self, other: Number) -> Result<Number, LispError> {
+        self.divide_integers(other, i64::checked_rem, |a, b| a % b, |a, b| a % b)
+    }
+
+    /// The remainder of integer division, with the sign of `other`.
+    pub fn modulo(self, other: Number) -> Result<Number, LispError> {
+        self.divide_integers(
+            other,
+            |a, b| a.checked_rem(b).map(|_| a.mod_floor(&b)),
+            |a, b| a.mod_floor(&b),
+            |a, b| a - b * (a / b).floor(),
+        )
+    }
+
+    /// The greatest common divisor, which is never negative.
+    pub fn gcd(self, other: Number) -> Result<Number, LispError> {
+        self.check_integer()?;
+        other.check_integer()?;
+        // the gcd of two `i64::MIN`s is too big for an `i64`
+        Ok(integer_op(
+            self,
+            other,
+            |a, b| Some(a.checked_abs()?.gcd(&b.checked_abs()?)),
+            |a, b| a.gcd(&b),
+            |mut a, mut b| {
+                while b != 0.0 {
+                    let r = a % b;
+                    a = b;
+                    b = r;
+                }
+                a.abs()
+            },
+        ))
+    }
+
+    /// Raise `self` to the power `exponent`, exactly if `self` is exact and
+    /// `exponent` is an exact integer.
+    pub fn expt(self, exponent: Number) -> Result<Number, LispError> {
+        let e = match exponent {
+            Number::Int(e) if self.is_exact() => e,
+            Number::Big(e) if self.is_exact() => {
+                return Err(LispError::InvalidArgument(format!(
+                    "exponent too large: {}",
+                    e
+                )))
+            }
+            _ => return Ok(Number::Real(self.to_f64().powf(exponent.to_f64()))),
+        };
+        let too_large = || LispError::InvalidArgument(format!("exponent too large: {}", e));
+        if let Number::Int(n) = self {
+            if let Some(n) = u32::try_from(e).ok().and_then(|e| n.checked_pow(e)) {
+                return Ok(Number::Int(n));
+            }
+        }
+        if e < 0 && self.is_zero() {
+            return Err(division_by_zero());
+        }
+        let e = i32::try_from(e).map_err(|_| too_large())?;
+        Ok(Number::from_ratio(self.to_ratio().unwrap().pow(e)))
+    }
+
+    fn check_integer(&self) -> Result<(), LispError> {
+        match self {
+            Number::Int(_) | Number::Big(_) => Ok(()),
+            Number::Real(x) if x.fract() == 0.0 => Ok(()),
+            _ => Err(LispError::InvalidArgument(format!(
+                "not an integer: {}",
+                self
+            ))),
+        }
+    }
+
+    /// The numerator of this number in lowest terms.
+    pub fn numerator(self) -> Result<Number, LispError> {
+        self.map_ratio(|r| BigRational::from_integer(r.numer().clone()))
+    }
+
+    /// The denominator of this number in lowest terms, which is always positive.
+    pub fn denominator(self) -> Result<Number, LispError> {
+        self.map_ratio(|r| BigRational::from_integer(r.denom().clone()))
+    }
+
+    /// Apply `f` to the exact value of this number, giving a result that is
+    /// as exact as this number.
+    fn map_ratio(self, f: impl FnOnce(&BigRational) -> BigRational) -> Result<Number, LispError> {
+        let exact = self.is_exact();
+        let result = Number::from_ratio(f(&self.into_exact()?.to_ratio().unwrap()));
+        Ok(if exact { result } else { result.into_inexact() })
+    }
+
+    /// The simplest rational number that differs from `self` by no more
+    /// than `tolerance`.
+    pub fn rationalize(self, tolerance: Number) -> Result<Number, LispError> {
+        let exact = self.is_exact() && tolerance.is_exact();
+        let x = self.into_exact()?.to_ratio().unwrap();
+        let y = tolerance.into_exact()?.to_ratio().unwrap().abs();
+        let result = Number::from_ratio(simplest_between(&(&x - &y), &(&x + &y)));
+        Ok(if exact { result } else { result.into_inexact() })
+    }
+
+    /// Numeric equality, regardless of exactness.
+    pub fn num_eq(&self, other: &Number) -> bool {
+        match (self.to_ratio(), other.to_ratio()) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.to_f64() == other.to_f64(),
+        }
+    }
+}
+
+/// Combine two integers with `small` if both are `Int`s and it doesn't
+/// overflow, with `big` if both are exact, and with `inexact` otherwise.
+fn integer_op(
+    a: Number,
+    b: Number,
+    small: fn(i64, i64) -> Option<i64>,
+    big: fn(BigInt, BigInt) -> BigInt,
+    inexact: fn(f64, f64) -> f64,
+) -> Number {
+    if let (Number::Int(a), Number::Int(b)) = (&a, &b) {
+        if let Some(n) = small(*a, *b) {
+            return Number::Int(n);
+        }
+    }
+    match (a.to_big(), b.to_big()) {
+        (Some(a), Some(b)) => Number::from_big(big(a, b)),
+        _ => Number::Real(inexact(a.to_f64(), b.to_f64())),
+    }
+}
+
+/// The rational with the smallest denominator in the interval from `lo` to
+/// `hi`, found by comparing their continued fractions.
+fn simplest_between(lo: &BigRational, hi: &BigRational) -> BigRational {
+    if lo.is_positive() {
+        let floor = lo.floor();
+        if &floor == lo {
+            floor
+        } else if floor < hi.floor() {
+            floor + BigRational::one()
+        } else {
+            floor.clone() + simplest_between(&(hi - &floor).recip(), &(lo - &floor).recip()).recip()
+        }
+    } else if hi.is_negative() {
+        -simplest_between(&-hi, &-lo)
+    } else {
+        BigRational::zero()
+    }
+}
+
+fn division_by_zero() -> LispError {
+    LispError::InvalidArgument("division by zero".to_string())
+}
+
+impl Add for Number {
+    type Output = Number;
+
+    fn add(self, other: Number) -> Number {
+        self.combine(other, i64::checked_add, |a, b| a + b, |a, b| a + b)
+    }
+}
+
+impl Sub for Number {
+    type Output = Number;
+
+    fn sub(self, other: Number) -> Number {
+        self.combine(other, i64::checked_sub, |a, b| a - b, |a, b| a - b)
+    }
+}
+
+impl Mul for Number {
+    type Output = Number;
+
+    fn mul(self, other: Number) -> Number {
+        self.combine(other, i64::checked_mul, |a, b| a * b, |a, b| a * b)
+    }
+}
+
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        match self {
+            Number::Int(n) => match n.checked_neg() {
+                Some(n) => Number::Int(n),
+                None => Number::from_big(-BigInt::from(n)),
+            },
+            Number::Big(n) => Number::from_big(-&*n),
+            Number::Rat(r) => Number::Rat(Rc::new(-&*r)),
+            Number::Real(x) => Number::Real(-x),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Number::Int(n) => write!(f, "{}", n),
+            Number::Big(ref n) => write!(f, "{}", n),
+            Number::Rat(ref r) => write!(f, "{}", r),
+            Number::Real(x) if x.is_nan() => write!(f, "+nan.0"),
+            Number::Real(x) if x.is_infinite() => {
+                write!(f, "{}inf.0", if x > 0.0 { "+" } else { "-" })
+            }
+            // keep a decimal point so that it reads back as inexact
+            Number::Real(x) if x.fract() == 0.0 => write!(f, "{}.0", x),
+            Number::Real(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// Parse `text` as a number literal, including any `#x`/`#e`-style prefixes.
+pub fn parse_number(text: &str) -> Option<Number> {
+    match text.as_bytes().first()? {
+        b'0'..=b'9' | b'+' | b'-' | b'.' | b'#' => {}
+        // most identifiers
+        _ => return None,
+    }
+    if let Some(num) = parse_small_integer(text) {
+        return Some(num);
+    }
+    let mut radix = None;
+    let mut exactness = None;
+    let mut rest = text;
+    while let Some(prefix) = rest.strip_prefix('#') {
+        let mut chars = prefix.chars();
+        match chars.next()?.to_ascii_lowercase() {
+            'b' if radix.is_none() => radix = Some(2),
+            'o' if radix.is_none() => radix = Some(8),
+            'd' if radix.is_none() => radix = Some(10),
+            'x' if radix.is_none() => radix = Some(16),
+            ch @ 'e' | ch @ 'i' if exactness.is_none() => exactness = Some(ch),
+            _ => return None,
+        }
+        rest = chars.as_str();
+    }
+    let num = parse_real(rest, radix.unwrap_or(10), exactness == Some('e'))?;
+    match exactness {
+        Some('e') => num.into_exact().ok(),
+        Some('i') => Some(num.into_inexact()),
+        _ => Some(num),
+    }
+}
+
+/// Parse a decimal integer with no prefix, short enough that it can't
+/// overflow. These are most of the numbers in real code, so they skip the
+/// general grammar.
+fn parse_small_integer(text: &str) -> Option<Number> {
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+    if digits.is_empty() || digits.len() > 18 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let magnitude = digits
+        .bytes()
+        .fold(0i64, |acc, b| acc * 10 + i64::from(b - b'0'));
+    Some(Number::Int(if text.starts_with('-') {
+        -magnitude
+    } else {
+        magnitude
+    }))
+}
+
+/// return true if `text` can only be read as a number, so that failing to
+/// parse it is an error rather than a sign that it is an identifier.
+pub fn looks_numeric(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some('#') => true,
+        Some(ch) if ch.is_ascii_digit() => true,
+        Some('+') | Some('-') => match chars.next() {
+            Some(ch) if ch.is_ascii_digit() => true,
+            Some('.') => chars.next().is_some_and(|ch| ch.is_ascii_digit()),
+            _ => false,
+        },
+        Some('.') => chars.next().is_some_and(|ch| ch.is_ascii_digit()),
+        _ => false,
+    }
+}
+
+/// Parse a real in `radix`, reading decimals exactly if `exact` is set.
+fn parse_real(text: &str, radix: u32, exact: bool) -> Option<Number> {
+    let special = [
+        ("+inf.0", f64::INFINITY),
+        ("-inf.0", f64::NEG_INFINITY),
+        ("+nan.0", f64::NAN),
+        ("-nan.0", f64::NAN),
+    ];
+    if let Some(&(_, num)) = special.iter().find(|(name, _)| name.eq_ignore_ascii_case(text)) {
+        return Some(Number::Real(num));
+    }
+    let (negative, body) = match text.as_bytes().first()? {
+        b'+' => (false, &text[1..]),
+        b'-' => (true, &text[1..]),
+        _ => (false, text),
+    };
+    let magnitude = if let Some((numerator, denominator)) = body.split_once('/') {
+        let numerator = parse_uinteger(numerator, radix)?;
+        match parse_uinteger(denominator, radix)? {
+            Number::Int(0) => return None,
+            denominator => numerator.try_div(denominator).ok()?,
+        }
+    } else if let Some(integer) = parse_uinteger(body, radix) {
+        integer
+    } else if radix == 10 && is_decimal(body) {
+        if exact {
+            parse_exact_decimal(body)?
+        } else {
+            Number::Real(body.parse().ok()?)
+        }
+    } else {
+        return None;
+    };
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+/// The largest power of ten that an exact decimal literal like `#e1e9` may
+/// be scaled by. Larger ones would take a long time to compute, so they are
+/// malformed instead.
+const MAX_EXACT_EXPONENT: i32 = 10_000;
+
+/// Parse `text`, which matches `<decimal 10>`, as an exact number.
+fn parse_exact_decimal(text: &str) -> Option<Number> {
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(index) => (&text[..index], text[index + 1..].parse::<i32>().ok()?),
+        None => (text, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = BigInt::parse_bytes(format!("0{}{}", integer, fraction).as_bytes(), 10)?;
+    let scale = exponent.checked_sub(i32::try_from(fraction.len()).ok()?)?;
+    if scale.abs() > MAX_EXACT_EXPONENT {
+        return None;
+    }
+    let ten = BigRational::from_integer(BigInt::from(10));
+    Some(Number::from_ratio(
+        BigRational::from_integer(digits) * ten.pow(scale),
+    ))
+}
+
+/// Parse digits in `radix` as an exact integer.
+fn parse_uinteger(text: &str, radix: u32) -> Option<Number> {
+    if text.is_empty() || !text.chars().all(|ch| ch.is_digit(radix)) {
+        return None;
+    }
+    BigInt::parse_bytes(text.as_bytes(), radix).map(Number::from_big)
+}
+
+/// return true if `text` matches `<decimal 10>`: digits with an optional
+/// fraction and exponent, with at least one digit before the exponent.
+fn is_decimal(text: &str) -> bool {
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(index) => (&text[..index], Some(&text[index + 1..])),
+        None => (text, None),
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (mantissa, ""),
+    };
+    let all_digits = |s: &str| s.chars().all(|ch| ch.is_ascii_digit());
+    let mantissa_ok =
+        all_digits(integer) && all_digits(fraction) && !(integer.is_empty() && fraction.is_empty());
+    let exponent_ok = match exponent {
+        Some(exponent) => {
+            let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            !digits.is_empty() && all_digits(digits)
+        }
+        None => true,
+    };
+    mantissa_ok && exponent_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(n: &str) -> Number {
+        Number::from_big(n.parse().unwrap())
+    }
+
+    #[test]
+    fn overflow_promotes_to_big() {
+        let sum = Number::Int(i64::MAX) + Number::Int(1);
+        assert_eq!(sum, big("9223372036854775808"));
+        assert!(matches!(sum, Number::Big(_)));
+        let quotient = Number::Int(i64::MIN).try_div(Number::Int(-1)).unwrap();
+        assert_eq!(quotient, big("9223372036854775808"));
+        let quotient = Number::Int(i64::MIN).quotient(Number::Int(-1)).unwrap();
+        assert_eq!(quotient, big("9223372036854775808"));
+    }
+
+    #[test]
+    fn results_that_fit_demote_to_int() {
+        let n = Number::Int(2).expt(Number::Int(100)).unwrap();
+        assert!(matches!(n, Number::Big(_)));
+        assert!(matches!(n.clone() - n.clone(), Number::Int(0)));
+        let divisor = Number::Int(2).expt(Number::Int(98)).unwrap();
+        assert_eq!(n.try_div(divisor).unwrap(), Number::Int(4));
+        let min = Number::Int(i64::MIN);
+        assert_eq!(min - Number::Int(-1), Number::Int(i64::MIN + 1));
+    }
+
+    #[test]
+    fn gcd_of_min_int() {
+        let min = Number::Int(i64::MIN);
+        let two_63 = big("9223372036854775808");
+        assert_eq!(min.clone().gcd(min.clone()).unwrap(), two_63);
+        assert_eq!(min.clone().gcd(Number::Int(0)).unwrap(), two_63);
+        assert_eq!(min.gcd(Number::Int(6)).unwrap(), Number::Int(2));
+    }
+
+    fn ratio(n: i64, d: i64) -> BigRational {
+        BigRational::new(BigInt::from(n), BigInt::from(d))
+    }
+
+    #[test]
+    fn rationalize_r7rs_examples() {
+        let x = Number::from_ratio(ratio(3, 10));
+        let y = Number::from_ratio(ratio(1, 10));
+        let third = Number::from_ratio(ratio(1, 3));
+        assert_eq!(x.rationalize(y.clone()).unwrap(), third);
+        assert_eq!(
+            Number::Real(0.3).rationalize(y).unwrap(),
+            Number::Real(1.0 / 3.0)
+        );
+    }
+
+    #[test]
+    fn simplest_between_negative_intervals() {
+        assert_eq!(
+            simplest_between(&ratio(-4, 10), &ratio(-2, 10)),
+            ratio(-1, 3)
+        );
+        assert_eq!(simplest_between(&ratio(-7, 2), &ratio(-3, 1)), ratio(-3, 1));
+        assert_eq!(simplest_between(&ratio(-1, 2), &ratio(1, 3)), ratio(0, 1));
+    }
+
+    #[test]
+    fn exact_decimals() {
+        assert_eq!(parse_number("#e1.5"), Some(Number::from_ratio(ratio(3, 2))));
+        assert_eq!(parse_number("#e-1.5e2"), Some(Number::Int(-150)));
+        assert_eq!(
+            parse_number("#e25e-2"),
+            Some(Number::from_ratio(ratio(1, 4)))
+        );
+    }
+
+    #[test]
+    fn exact_decimal_exponent_limit() {
+        assert!(parse_number("#e1e10000").is_some());
+        assert_eq!(parse_number("#e1e10001"), None);
+        assert_eq!(parse_number("#e1e-10001"), None);
+        assert_eq!(parse_number("#e1e100000000"), None);
+    }
+}
@@ -1,77 +1,423 @@
-use crate::lexer::Token;
+use crate::builtins::apply;
+use crate::error::LispError;
+use crate::lexer::{is_dispatch_macro_char, Span, Token};
+use crate::number::Number;
+use crate::symbol::Symbol;
 use crate::value::{RefValue, Value};
 
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::iter::Peekable;
+use std::rc::Rc;
 
-pub fn parse<T>(token_stream: &mut Peekable<T>) -> Result<Value, String>
+/// Parse the next datum from `token_stream` and return it with where it
+/// starts, or return `None` if nothing but comments is left. Datum labels are
+/// local to the datum. After an error the rest of the malformed form is
+/// skipped, so that the next call starts at the next top-level form.
+pub fn parse<T>(
+    token_stream: &mut Peekable<T>,
+    suffix_follows: &Cell<bool>,
+) -> Option<Result<(Value, Span), LispError>>
 where
-    T: Iterator<Item = Result<Token, String>>,
+    T: Iterator<Item = Result<(Token, Span), LispError>>,
 {
-    let first_token = match token_stream.next() {
-        Some(item) => item?,
-        None => return Err("unexpected end of input".to_string()),
+    let mut parser = Parser {
+        token_stream,
+        suffix_follows,
+        labels: HashMap::new(),
+        depth: 0,
     };
-
-    let value = match first_token {
-        Token::LPER => {
-            if let Some(Ok(Token::RPER)) = token_stream.peek().cloned() {
-                token_stream.next();
-                Value::Null
-            } else {
-                parse_list(token_stream)?
+    let datum = match parser.skip_datum_comments() {
+        Ok(()) => match parser.token_stream.peek()? {
+            Ok((_, span)) => {
+                let span = span.clone();
+                parser.parse().map(|value| (value, span))
             }
-        }
-        Token::BOOL(b) => Value::Bool(b),
-        Token::IDENT(ident) => Value::Ident(ident),
-        Token::NUM(num) => Value::Num(num),
-        Token::QUOTE => {
-            let quoted = parse(token_stream)?;
-            Value::Cons(
-                RefValue::new(Value::Ident("quote".to_string())),
-                RefValue::new(Value::Cons(
-                    RefValue::new(quoted),
-                    RefValue::new(Value::Null),
-                )),
-            )
-        }
-        _ => return Err("syntax error".to_string()),
+            Err(_) => Err(parser.next_token()?.unwrap_err()),
+        },
+        Err(e) => Err(e),
     };
+    if let Err(e) = &datum {
+        if !matches!(e.kind(), LispError::Incomplete(_)) {
+            parser.skip_rest_of_form();
+        }
+    }
+    Some(datum)
+}
+
+thread_local! {
+    /// Reader macros: the procedure that translates the datum after `#ch`, keyed by `ch`.
+    static DISPATCH_MACROS: RefCell<HashMap<char, Value>> = RefCell::new(HashMap::new());
+}
+
+/// Whether `#ch` has a reader macro, so that the lexer reads it as a dispatch.
+pub(crate) fn is_dispatch_macro_defined(ch: char) -> bool {
+    DISPATCH_MACROS.with(|macros| macros.borrow().contains_key(&ch))
+}
+
+/// Make `#ch datum` read as the result of calling `procedure` on `datum`.
+/// `procedure` may be a closure or, for embedders, a `Value::Subr`.
+pub fn set_dispatch_macro(ch: char, procedure: Value) -> Result<(), LispError> {
+    if !is_dispatch_macro_char(ch) {
+        return Err(LispError::InvalidArgument(format!(
+            "cannot define a reader macro for #{}",
+            ch
+        )));
+    }
+    if procedure.type_name() != "procedure" {
+        return Err(procedure.type_error("procedure"));
+    }
+    DISPATCH_MACROS.with(|macros| macros.borrow_mut().insert(ch, procedure));
+    Ok(())
+}
 
-    Ok(value)
+struct Parser<'a, T: Iterator> {
+    token_stream: &'a mut Peekable<T>,
+    /// Whether a neoteric suffix follows the last token consumed, which
+    /// saves peeking at the next token when none does.
+    suffix_follows: &'a Cell<bool>,
+    /// Data defined by `#n=` so far. A label whose datum is still being read
+    /// maps to a placeholder that is patched once the datum is complete.
+    labels: HashMap<usize, Value>,
+    /// Open brackets consumed minus close brackets consumed.
+    depth: isize,
 }
 
-fn parse_list<T>(token_stream: &mut Peekable<T>) -> Result<Value, String>
+impl<'a, T> Parser<'a, T>
 where
-    T: Iterator<Item = Result<Token, String>>,
+    T: Iterator<Item = Result<(Token, Span), LispError>>,
 {
-    let mut tail = RefValue::new(Value::Null);
-    let head = parse(token_stream)?;
-    let head = Value::Cons(RefValue::new(head), tail.clone());
-    while let Some(peek) = token_stream.peek().cloned() {
-        match peek? {
-            Token::RPER => {
-                token_stream.next();
-                return Ok(head);
+    /// Consume the next token, keeping track of bracket nesting.
+    fn next_token(&mut self) -> Option<Result<(Token, Span), LispError>> {
+        let item = self.token_stream.next()?;
+        match item {
+            Ok((Token::LPER, _))
+            | Ok((Token::NEOLPER, _))
+            | Ok((Token::LVECTOR, _))
+            | Ok((Token::LBYTEVECTOR, _))
+            | Ok((Token::LBRACE, _))
+            | Ok((Token::NEOLBRACE, _)) => self.depth += 1,
+            Ok((Token::RPER, _)) | Ok((Token::RBRACE, _)) => self.depth -= 1,
+            _ => {}
+        }
+        Some(item)
+    }
+
+    /// Skip tokens until the brackets opened so far are closed again.
+    fn skip_rest_of_form(&mut self) {
+        while self.depth > 0 && self.next_token().is_some() {}
+    }
+
+    fn parse(&mut self) -> Result<Value, LispError> {
+        self.parse_expr(false)
+    }
+
+    /// Parse one datum. `neoteric` is set for the elements of a curly-infix list,
+    /// where `f(x)` means `(f x)` rather than two separate data.
+    fn parse_expr(&mut self, neoteric: bool) -> Result<Value, LispError> {
+        self.skip_datum_comments()?;
+        let (first_token, span) = match self.next_token() {
+            Some(item) => item?,
+            None => return Err(LispError::Incomplete("unexpected end of input".to_string())),
+        };
+
+        let mut value = match first_token {
+            Token::LPER | Token::NEOLPER => {
+                self.skip_datum_comments()?;
+                if let Some(Ok((Token::RPER, _))) = self.token_stream.peek() {
+                    self.next_token();
+                    Value::Null
+                } else {
+                    self.parse_list(&span)?
+                }
+            }
+            Token::LVECTOR => {
+                let mut items = Vec::new();
+                loop {
+                    self.skip_datum_comments()?;
+                    match self.token_stream.peek() {
+                        Some(Ok((Token::RPER, _))) => {
+                            self.next_token();
+                            break;
+                        }
+                        Some(_) => items.push(self.parse()?),
+                        None => return Err(unterminated("vector", &span)),
+                    }
+                }
+                Value::new_vector(items)
+            }
+            Token::LBYTEVECTOR => {
+                let mut bytes = Vec::new();
+                loop {
+                    self.skip_datum_comments()?;
+                    match self.next_token() {
+                        Some(item) => match item? {
+                            (Token::RPER, _) => break,
+                            (Token::NUM(Number::Int(n)), _) if (0..=255).contains(&n) => {
+                                bytes.push(n as u8)
+                            }
+                            (Token::NUM(num), span) => {
+                                return Err(LispError::Parse(format!("invalid byte: {}", num))
+                                    .at(span))
+                            }
+                            (token, span) => {
+                                return Err(LispError::Parse(format!(
+                                    "unexpected {} in bytevector",
+                                    token
+                                ))
+                                .at(span))
+                            }
+                        },
+                        None => return Err(unterminated("bytevector", &span)),
+                    }
+                }
+                Value::new_bytevector(bytes)
+            }
+            Token::LBRACE | Token::NEOLBRACE => {
+                let items = self.parse_curly_items(&span)?;
+                curly_infix(items, &span)
             }
-            Token::DOT => {
-                token_stream.next();
-                let value = parse(token_stream)?;
-                tail.replace(value);
-                if let Some(next) = token_stream.next() {
-                    if let Token::RPER = next? {
-                        return Ok(head);
+            Token::BOOL(b) => Value::Bool(b),
+            Token::IDENT(ident) => Value::Ident(ident),
+            Token::NUM(num) => Value::from(num),
+            Token::STRING(string) => Value::Str(string),
+            Token::CHAR(ch) => Value::Char(ch),
+            Token::QUOTE => return self.parse_abbreviation("quote", &span),
+            Token::QUASIQUOTE => return self.parse_abbreviation("quasiquote", &span),
+            Token::UNQUOTE => return self.parse_abbreviation("unquote", &span),
+            Token::UNQUOTESPLICING => return self.parse_abbreviation("unquote-splicing", &span),
+            Token::LABELDEF(n) => return self.parse_labelled(n, neoteric, span),
+            Token::DISPATCH(ch) => {
+                let procedure = DISPATCH_MACROS.with(|macros| macros.borrow().get(&ch).cloned());
+                let procedure = match procedure {
+                    Some(procedure) => procedure,
+                    None => {
+                        return Err(LispError::Parse(format!("unknown # syntax #{}", ch)).at(span))
                     }
+                };
+                let datum = self.parse()?;
+                apply(procedure, vec![datum])
+                    .map_err(|e| LispError::ReaderMacro(ch, Box::new(e)).at(span.clone()))?
+            }
+            Token::LABELREF(n) => match self.labels.get(&n) {
+                Some(value) => value.clone(),
+                None => {
+                    return Err(LispError::Parse(format!("undefined datum label #{}#", n)).at(span))
+                }
+            },
+            _ => return Err(LispError::Parse(format!("unexpected {}", first_token)).at(span)),
+        };
+
+        // neoteric suffixes: `f{x}` is `(f x)`, `f{}` is `(f)`, and in neoteric
+        // context `f(x y)` is `(f x y)`
+        while self.suffix_follows.get() {
+            match self.token_stream.peek() {
+                Some(Ok((Token::NEOLBRACE, _))) => {
+                    let (_, brace_span) = self.next_token().unwrap()?;
+                    let items = self.parse_curly_items(&brace_span)?;
+                    let args = if items.is_empty() {
+                        Value::Null
+                    } else {
+                        read_list(vec![curly_infix(items, &brace_span)])
+                    };
+                    value = make_list(value, args, &span);
+                }
+                Some(Ok((Token::NEOLPER, _))) if neoteric => {
+                    let (_, paren_span) = self.next_token().unwrap()?;
+                    self.skip_datum_comments()?;
+                    let args = if let Some(Ok((Token::RPER, _))) = self.token_stream.peek() {
+                        self.next_token();
+                        Value::Null
+                    } else {
+                        self.parse_list(&paren_span)?
+                    };
+                    value = make_list(value, args, &span);
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    /// Parse the datum after `#n=`. References to `n` inside the datum itself
+    /// are read as a placeholder and patched afterwards, which makes the
+    /// datum cyclic.
+    fn parse_labelled(&mut self, n: usize, neoteric: bool, span: Span) -> Result<Value, LispError> {
+        let placeholder = Value::new_vector(Vec::new());
+        self.labels.insert(n, placeholder.clone());
+        let datum = self.parse_expr(neoteric)?;
+        if datum == placeholder {
+            return Err(LispError::Parse(format!("datum label #{}= refers to itself", n)).at(span));
+        }
+        replace_placeholder(&datum, &placeholder, &datum, &mut HashSet::new());
+        self.labels.insert(n, datum.clone());
+        Ok(datum)
+    }
+
+    /// Parse the elements of a curly-infix list whose `{` was at `span`.
+    fn parse_curly_items(&mut self, span: &Span) -> Result<Vec<Value>, LispError> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_datum_comments()?;
+            match self.token_stream.peek() {
+                Some(Ok((Token::RBRACE, _))) => {
+                    self.next_token();
+                    return Ok(items);
+                }
+                Some(_) => items.push(self.parse_expr(true)?),
+                None => return Err(unterminated("curly-infix list", span)),
+            }
+        }
+    }
+
+    /// Parse the datum after an abbreviation like `'` and wrap it as `(name datum)`.
+    fn parse_abbreviation(&mut self, name: &str, span: &Span) -> Result<Value, LispError> {
+        let datum = self.parse()?;
+        Ok(make_list(
+            Value::Ident(Symbol::intern(name)),
+            read_list(vec![datum]),
+            span,
+        ))
+    }
+
+    /// Parse the rest of a list whose `(` was at `span`.
+    fn parse_list(&mut self, span: &Span) -> Result<Value, LispError> {
+        let mut tail = RefValue::new_read(Value::Null);
+        let head = RefValue::new_read(self.parse()?);
+        head.set_span(span.clone());
+        let head = Value::Cons(head, tail.clone());
+        loop {
+            self.skip_datum_comments()?;
+            let peek = match self.token_stream.peek().cloned() {
+                Some(peek) => peek,
+                None => break,
+            };
+            match peek? {
+                (Token::RPER, _) => {
+                    self.next_token();
+                    return Ok(head);
+                }
+                (Token::DOT, _) => {
+                    self.next_token();
+                    let value = self.parse()?;
+                    tail.replace(value);
+                    self.skip_datum_comments()?;
+                    return match self.next_token() {
+                        Some(next) => match next? {
+                            (Token::RPER, _) => Ok(head),
+                            (token, span) => {
+                                Err(LispError::Parse(format!("unexpected {}", token)).at(span))
+                            }
+                        },
+                        None => Err(unterminated("list", span)),
+                    };
+                }
+                _ => {
+                    let value = self.parse()?;
+                    let next_tail = RefValue::new_read(Value::Null);
+                    tail.replace(Value::Cons(RefValue::new_read(value), next_tail.clone()));
+                    tail = next_tail;
+                }
+            }
+        }
+        Err(unterminated("list", span))
+    }
+
+    /// Consume every `#;` at the head of the token stream along with the datum it comments out.
+    fn skip_datum_comments(&mut self) -> Result<(), LispError> {
+        while let Some(Ok((Token::DATUMCOMMENT, _))) = self.token_stream.peek() {
+            self.next_token();
+            self.parse()?;
+        }
+        Ok(())
+    }
+}
+
+/// Build a proper list of `items` out of cells made by the reader.
+fn read_list(items: Vec<Value>) -> Value {
+    items.into_iter().rev().fold(Value::Null, |acc, value| {
+        Value::Cons(RefValue::new_read(value), RefValue::new_read(acc))
+    })
+}
+
+/// Build `(head . rest)`, remembering that it was read from `span`.
+fn make_list(head: Value, rest: Value, span: &Span) -> Value {
+    let head = RefValue::new_read(head);
+    head.set_span(span.clone());
+    Value::Cons(head, RefValue::new_read(rest))
+}
+
+/// Translate the elements of a curly-infix list as SRFI 105 describes:
+/// `{}` is `()`, `{e}` is `e`, `{op e}` is `(op e)`, `{a op b op c}` is
+/// `(op a b c)`, and anything else is `($nfx$ ...)`.
+fn curly_infix(mut items: Vec<Value>, span: &Span) -> Value {
+    match items.len() {
+        0 => Value::Null,
+        1 => items.pop().unwrap(),
+        2 => make_list(items.remove(0), read_list(items), span),
+        len => {
+            let op = items[1].clone();
+            let simple = len % 2 == 1 && items.iter().skip(1).step_by(2).all(|item| *item == op);
+            if simple {
+                let operands = items.into_iter().step_by(2).collect();
+                make_list(op, read_list(operands), span)
+            } else {
+                make_list(
+                    Value::Ident(Symbol::intern("$nfx$")),
+                    read_list(items),
+                    span,
+                )
+            }
+        }
+    }
+}
+
+/// Replace every occurrence of `placeholder` inside `value` with `datum`.
+/// `seen` holds the pairs and vectors already visited, since `value` may
+/// already be cyclic through labels defined inside it.
+fn replace_placeholder(
+    value: &Value,
+    placeholder: &Value,
+    datum: &Value,
+    seen: &mut HashSet<usize>,
+) {
+    let mut value = value.clone();
+    loop {
+        match value {
+            Value::Cons(car, cdr) => {
+                if !seen.insert(car.id()) {
+                    return;
+                }
+                if car.to_value() == *placeholder {
+                    car.replace(datum.clone());
                 } else {
-                    return Err("syntax error".to_string());
+                    replace_placeholder(&car.to_value(), placeholder, datum, seen);
                 }
+                if cdr.to_value() == *placeholder {
+                    cdr.replace(datum.clone());
+                    return;
+                }
+                value = cdr.to_value();
             }
-            _ => {
-                let value = parse(token_stream)?;
-                let next_tail = RefValue::new(Value::Null);
-                tail.replace(Value::Cons(RefValue::new(value), next_tail.clone()));
-                tail = next_tail;
+            Value::Vector(vector) => {
+                if !seen.insert(Rc::as_ptr(&vector) as usize) {
+                    return;
+                }
+                let items = vector.borrow().clone();
+                for (i, item) in items.iter().enumerate() {
+                    if item == placeholder {
+                        vector.borrow_mut()[i] = datum.clone();
+                    } else {
+                        replace_placeholder(item, placeholder, datum, seen);
+                    }
+                }
+                return;
             }
+            _ => return,
         }
     }
-    return Err("syntax error".to_string());
+}
+
+fn unterminated(what: &str, span: &Span) -> LispError {
+    LispError::Incomplete(format!("unterminated {}", what)).at(span.clone())
 }
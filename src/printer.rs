@@ -0,0 +1,266 @@
+use crate::lexer::{needs_bars, CHAR_NAMES};
+use crate::number::Number;
+use crate::value::Value;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Which pairs and vectors `write` marks with datum labels.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Sharing {
+    /// Only those that are part of a cycle, so that the output is finite.
+    Cycles,
+    /// Every one that is reached more than once.
+    All,
+}
+
+/// How strings, characters and symbols are printed.
+#[derive(Clone, Copy, PartialEq)]
+enum Style {
+    /// As the reader reads them back, for `write`.
+    Write,
+    /// As plain text, for `display`.
+    Display,
+}
+
+/// Formats a value as `write` prints it, with `#n=` and `#n#` labels for
+/// shared structure as chosen by `sharing`.
+pub struct Written<'a> {
+    value: &'a Value,
+    sharing: Sharing,
+}
+
+impl<'a> Written<'a> {
+    pub fn new(value: &'a Value, sharing: Sharing) -> Self {
+        Written { value, sharing }
+    }
+}
+
+impl fmt::Display for Written<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer::new(self.value, self.sharing, Style::Write).print(self.value, f)
+    }
+}
+
+/// Formats a value as `display` prints it: strings and characters as their
+/// contents and symbols without bars. Cycles are still labelled, so that the
+/// output is finite.
+pub struct Displayed<'a>(pub &'a Value);
+
+impl fmt::Display for Displayed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer::new(self.0, Sharing::Cycles, Style::Display).print(self.0, f)
+    }
+}
+
+/// The identity of a pair or vector, for detecting shared structure.
+fn object_id(value: &Value) -> Option<usize> {
+    match value {
+        Value::Cons(car, _) => Some(car.id()),
+        Value::Vector(vector) => Some(Rc::as_ptr(vector) as usize),
+        _ => None,
+    }
+}
+
+/// Find the pairs and vectors in `value` that need a label. Each maps to
+/// `None` until its label number is assigned while printing.
+fn find_labels(value: &Value, sharing: Sharing) -> HashMap<usize, Option<usize>> {
+    enum State {
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        value: &Value,
+        sharing: Sharing,
+        states: &mut HashMap<usize, State>,
+        labels: &mut HashMap<usize, Option<usize>>,
+    ) {
+        // the pairs along the spine of a list stay `Visiting` until the whole
+        // list is done, so a reference back to any of them is a cycle
+        let mut spine = Vec::new();
+        let mut value = value.clone();
+        while let Some(id) = object_id(&value) {
+            match states.get(&id) {
+                Some(State::Visiting) => {
+                    labels.insert(id, None);
+                    break;
+                }
+                Some(State::Done) => {
+                    if sharing == Sharing::All {
+                        labels.insert(id, None);
+                    }
+                    break;
+                }
+                None => {}
+            }
+            states.insert(id, State::Visiting);
+            spine.push(id);
+            match value {
+                Value::Cons(car, cdr) => {
+                    visit(&car.to_value(), sharing, states, labels);
+                    value = cdr.to_value();
+                }
+                Value::Vector(vector) => {
+                    for item in vector.borrow().iter() {
+                        visit(item, sharing, states, labels);
+                    }
+                    break;
+                }
+                _ => unreachable!(),
+            }
+        }
+        for id in spine {
+            states.insert(id, State::Done);
+        }
+    }
+
+    let mut labels = HashMap::new();
+    visit(value, sharing, &mut HashMap::new(), &mut labels);
+    labels
+}
+
+struct Printer {
+    labels: HashMap<usize, Option<usize>>,
+    next_label: usize,
+    style: Style,
+}
+
+impl Printer {
+    fn new(value: &Value, sharing: Sharing, style: Style) -> Self {
+        Printer {
+            labels: find_labels(value, sharing),
+            next_label: 0,
+            style,
+        }
+    }
+
+    /// Write the label of `value` if it has one: `#n#` if it was already
+    /// printed, in which case this returns true, or `#n=` the first time.
+    fn label(&mut self, value: &Value, f: &mut fmt::Formatter<'_>) -> Result<bool, fmt::Error> {
+        let labels = &mut self.labels;
+        let slot = match object_id(value).and_then(|id| labels.get_mut(&id)) {
+            Some(slot) => slot,
+            None => return Ok(false),
+        };
+        match slot {
+            Some(n) => {
+                write!(f, "#{}#", n)?;
+                Ok(true)
+            }
+            None => {
+                *slot = Some(self.next_label);
+                write!(f, "#{}=", self.next_label)?;
+                self.next_label += 1;
+                Ok(false)
+            }
+        }
+    }
+
+    fn print(&mut self, value: &Value, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.label(value, f)? {
+            return Ok(());
+        }
+        match value {
+            Value::Cons(car, cdr) => {
+                write!(f, "(")?;
+                self.print(&car.to_value(), f)?;
+                let mut rest = cdr.to_value();
+                loop {
+                    match rest {
+                        Value::Null => break,
+                        // a labelled pair can't be spliced into the list, since
+                        // its label has to go in front of its own parenthesis
+                        Value::Cons(car, cdr) if !self.labels.contains_key(&car.id()) => {
+                            write!(f, " ")?;
+                            self.print(&car.to_value(), f)?;
+                            rest = cdr.to_value();
+                        }
+                        other => {
+                            write!(f, " . ")?;
+                            self.print(&other, f)?;
+                            break;
+                        }
+                    }
+                }
+                write!(f, ")")
+            }
+            Value::Vector(vector) => {
+                write!(f, "#(")?;
+                for (i, item) in vector.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    self.print(item, f)?;
+                }
+                write!(f, ")")
+            }
+            atom => write_atom(atom, self.style, f),
+        }
+    }
+}
+
+/// Write a value that contains no other values.
+fn write_atom(value: &Value, style: Style, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match value {
+        Value::Str(string) if style == Style::Display => write!(f, "{}", string),
+        Value::Char(ch) if style == Style::Display => write!(f, "{}", ch),
+        Value::Ident(ident) if style == Style::Display => write!(f, "{}", ident),
+        Value::Null => write!(f, "()"),
+        Value::Bool(b) => write!(f, "{}", if *b { "#t" } else { "#f" }),
+        Value::Int(n) => write!(f, "{}", Number::Int(*n)),
+        Value::Big(n) => write!(f, "{}", n),
+        Value::Rat(r) => write!(f, "{}", r),
+        Value::Num(num) => write!(f, "{}", Number::Real(*num)),
+        Value::Str(string) => {
+            write!(f, "\"")?;
+            for ch in string.chars() {
+                match ch {
+                    '"' => write!(f, "\\\"")?,
+                    '\\' => write!(f, "\\\\")?,
+                    '\n' => write!(f, "\\n")?,
+                    '\t' => write!(f, "\\t")?,
+                    '\r' => write!(f, "\\r")?,
+                    ch if ch.is_control() => write!(f, "\\x{:x};", ch as u32)?,
+                    ch => write!(f, "{}", ch)?,
+                }
+            }
+            write!(f, "\"")
+        }
+        Value::Char(ch) => match CHAR_NAMES.iter().find(|&&(_, c)| c == *ch) {
+            Some((name, _)) => write!(f, "#\\{}", name),
+            None if ch.is_control() => write!(f, "#\\x{:x}", *ch as u32),
+            None => write!(f, "#\\{}", ch),
+        },
+        Value::Bytevector(bytes) => {
+            write!(f, "#u8(")?;
+            for (i, byte) in bytes.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "{}", byte)?;
+            }
+            write!(f, ")")
+        }
+        Value::Ident(ident) if !ident.is_interned() => write!(f, "#:{}", ident),
+        Value::Ident(ident) if needs_bars(&ident.name()) => {
+            write!(f, "|")?;
+            for ch in ident.name().chars() {
+                match ch {
+                    '|' => write!(f, "\\|")?,
+                    '\\' => write!(f, "\\\\")?,
+                    ch if ch.is_control() => write!(f, "\\x{:x};", ch as u32)?,
+                    ch => write!(f, "{}", ch)?,
+                }
+            }
+            write!(f, "|")
+        }
+        Value::Ident(ident) => write!(f, "{}", ident),
+        Value::Syntax(name, _) => write!(f, "#<syntax {}>", name),
+        Value::Closure(a, b, _) => write!(f, "#<closure {:?} {:?}>", a, b),
+        Value::Subr(name, _) => write!(f, "#<subr {}>", name),
+        Value::Cont(_vm) => write!(f, "#<subr continuation>"),
+        Value::Cons(_, _) | Value::Vector(_) => unreachable!(),
+    }
+}
@@ -0,0 +1,236 @@
+use crate::error::LispError;
+use crate::lexer::{Lexer, Source, Span, StrSource};
+use crate::parser::parse;
+use crate::value::Value;
+
+use std::cell::{Cell, RefCell};
+use std::io::BufRead;
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::vec;
+
+/// Reads data one at a time from a stream of characters. As an iterator it
+/// yields each datum, or the error that stopped it, until the input ends.
+pub struct Reader<'a, S: Source<'a>> {
+    lexer: Peekable<Lexer<'a, S>>,
+    suffix_follows: Rc<Cell<bool>>,
+    datum_span: Option<Span>,
+    /// Where `BufReadChars` leaves an I/O error that ended its input early.
+    io_error: Rc<RefCell<Option<LispError>>>,
+}
+
+impl<C: Iterator<Item = char>> Reader<'static, Peekable<C>> {
+    /// Read from `chars`, naming `file` in the spans of what is read.
+    pub fn new(chars: C, file: &str) -> Self {
+        Reader::with_lexer(Lexer::new(chars, file))
+    }
+}
+
+impl<'a> Reader<'a, StrSource<'a>> {
+    /// Read from `text`, naming `file` in the spans of what is read. This is
+    /// faster than reading its characters with `new`.
+    pub fn new_str(text: &'a str, file: &str) -> Self {
+        Reader::with_lexer(Lexer::new_str(text, file))
+    }
+
+    /// Read from `bytes`, which must be UTF-8, like `new_str`.
+    pub fn new_bytes(bytes: &'a [u8], file: &str) -> Result<Self, LispError> {
+        Ok(Reader::with_lexer(Lexer::new_bytes(bytes, file)?))
+    }
+
+    /// Read every datum in `source`.
+    pub fn read_str(source: &'a str) -> Result<Vec<Value>, LispError> {
+        Reader::new_str(source, "<string>").collect()
+    }
+}
+
+impl<R: BufRead> Reader<'static, Peekable<BufReadChars<R>>> {
+    /// Read from `reader`, naming `file` in the spans of what is read.
+    pub fn from_buf_read(reader: R, file: &str) -> Self {
+        let chars = BufReadChars {
+            reader,
+            line: Vec::new().into_iter(),
+            error: Rc::new(RefCell::new(None)),
+        };
+        let io_error = chars.error.clone();
+        Reader {
+            io_error,
+            ..Reader::with_lexer(Lexer::new(chars, file))
+        }
+    }
+}
+
+impl<'a, S: Source<'a>> Reader<'a, S> {
+    fn with_lexer(lexer: Lexer<'a, S>) -> Self {
+        Reader {
+            suffix_follows: lexer.suffix_follows(),
+            datum_span: None,
+            lexer: lexer.peekable(),
+            io_error: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Read past whitespace, comments and directives to the first token of
+    /// the next datum, if there is one. A REPL can use this to tell when the
+    /// user has started to type a datum.
+    pub fn skip_atmosphere(&mut self) {
+        self.lexer.peek();
+    }
+
+    /// Read the next datum, or return `None` at the end of the input. Input
+    /// that ends in the middle of a datum is an `Incomplete` error.
+    pub fn read_one(&mut self) -> Option<Result<Value, LispError>> {
+        let error = match parse(&mut self.lexer, &self.suffix_follows) {
+            Some(Ok((datum, span))) => {
+                self.datum_span = Some(span);
+                return Some(Ok(datum));
+            }
+            Some(Err(e)) => Some(Err(e)),
+            None => None,
+        };
+        // an I/O error explains whatever the truncated input did to the parse
+        match self.io_error.borrow_mut().take() {
+            Some(e) => Some(Err(e)),
+            None => error,
+        }
+    }
+
+    /// Where the datum last returned by `read_one` starts.
+    pub fn datum_span(&self) -> Option<&Span> {
+        self.datum_span.as_ref()
+    }
+}
+
+impl<'a, S: Source<'a>> Iterator for Reader<'a, S> {
+    type Item = Result<Value, LispError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_one()
+    }
+}
+
+/// The characters of a `BufRead`, read a line at a time with line endings
+/// kept. Reading stops at the first I/O error, which is left in `error`.
+pub struct BufReadChars<R> {
+    reader: R,
+    line: vec::IntoIter<char>,
+    error: Rc<RefCell<Option<LispError>>>,
+}
+
+impl<R: BufRead> Iterator for BufReadChars<R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if let Some(ch) = self.line.next() {
+                return Some(ch);
+            }
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => self.line = line.chars().collect::<Vec<_>>().into_iter(),
+                Err(e) => {
+                    *self.error.borrow_mut() = Some(LispError::Io(e.to_string()));
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::{Sharing, Written};
+
+    use std::io::{self, Read};
+
+    fn written(values: &[Value]) -> Vec<String> {
+        values
+            .iter()
+            .map(|value| Written::new(value, Sharing::Cycles).to_string())
+            .collect()
+    }
+
+    /// Lines of text followed by an I/O error.
+    struct Failing(io::Cursor<&'static str>);
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            unreachable!()
+        }
+    }
+
+    impl BufRead for Failing {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            if self.0.position() as usize == self.0.get_ref().len() {
+                return Err(io::Error::other("disk on fire"));
+            }
+            self.0.fill_buf()
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.0.consume(amt)
+        }
+    }
+
+    #[test]
+    fn read_str_reads_every_datum() {
+        let values = Reader::read_str("(a . b) #(1 2) \"s\" ; done\n").unwrap();
+        assert_eq!(written(&values), ["(a . b)", "#(1 2)", "\"s\""]);
+    }
+
+    #[test]
+    fn read_str_reports_incomplete_input() {
+        let error = Reader::read_str("(a\n (b").unwrap_err();
+        assert!(matches!(error.kind(), LispError::Incomplete(_)));
+        assert_eq!(error.span().map(|span| span.line), Some(2));
+    }
+
+    #[test]
+    fn datum_span_is_where_the_datum_starts() {
+        let mut reader = Reader::new_str("#;(skipped)\n  foo (bar)", "<string>");
+        reader.next().unwrap().unwrap();
+        assert_eq!(
+            reader.datum_span().map(ToString::to_string),
+            Some("<string>:2:3".to_string())
+        );
+        reader.next().unwrap().unwrap();
+        assert_eq!(reader.datum_span().map(|span| span.column), Some(7));
+    }
+
+    #[test]
+    fn new_reads_characters() {
+        let values: Result<Vec<_>, _> = Reader::new("x (y)".chars(), "<chars>").collect();
+        assert_eq!(written(&values.unwrap()), ["x", "(y)"]);
+    }
+
+    #[test]
+    fn from_buf_read_reads_across_lines() {
+        let input = io::Cursor::new("(a\r\n b)\n\"x\ny\" \"z\\\r\n  w\"\n)");
+        let mut reader = Reader::from_buf_read(input, "<buf>");
+        let values = (&mut reader)
+            .take(3)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(written(&values), ["(a b)", "\"x\\ny\"", "\"zw\""]);
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.span().map(|span| span.line), Some(6));
+    }
+
+    #[test]
+    fn fold_case_lasts_across_reads() {
+        let input = io::Cursor::new("#!fold-case\nHELLO\n(Quote ABC)\n#!no-fold-case\nXyz\n");
+        let mut reader = Reader::from_buf_read(input, "<stdin>");
+        let values = (&mut reader).map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(written(&values), ["hello", "(quote abc)", "Xyz"]);
+    }
+
+    #[test]
+    fn from_buf_read_reports_io_errors() {
+        let mut reader = Reader::from_buf_read(Failing(io::Cursor::new("1 (2\n")), "<buf>");
+        assert_eq!(written(&[reader.next().unwrap().unwrap()]), ["1"]);
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error, LispError::Io("disk on fire".to_string()));
+    }
+}
@@ -0,0 +1,111 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// A symbol: a handle to a name in the symbol table, or the number of a
+/// symbol made by `gensym`. Interned symbols with the same name are the same
+/// pointer, so symbols are cheap to copy, compare and hash.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(Name);
+
+#[derive(Clone, Copy)]
+enum Name {
+    Interned(&'static str),
+    /// The `n`th symbol made by `gensym`, named `gn`. Uninterned symbols are
+    /// never looked up by name, so the name is not kept.
+    Uninterned(usize),
+}
+
+thread_local! {
+    /// Every interned name. Names are leaked, since a symbol may be read again
+    /// at any time and has to be the same pointer when it is.
+    static SYMBOLS: RefCell<HashSet<&'static str>> = RefCell::new(HashSet::new());
+    static GENSYM_COUNTER: Cell<usize> = const { Cell::new(0) };
+}
+
+impl Symbol {
+    /// The symbol named `name`.
+    pub fn intern(name: &str) -> Symbol {
+        SYMBOLS.with(|symbols| {
+            let mut symbols = symbols.borrow_mut();
+            if let Some(&name) = symbols.get(name) {
+                return Symbol(Name::Interned(name));
+            }
+            let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+            symbols.insert(name);
+            Symbol(Name::Interned(name))
+        })
+    }
+
+    /// A new symbol that is not interned, so it differs from every other
+    /// symbol even if one has the same name.
+    pub fn gensym() -> Symbol {
+        let n = GENSYM_COUNTER.with(|counter| {
+            counter.set(counter.get() + 1);
+            counter.get()
+        });
+        Symbol(Name::Uninterned(n))
+    }
+
+    pub fn name(self) -> Cow<'static, str> {
+        match self.0 {
+            Name::Interned(name) => Cow::Borrowed(name),
+            Name::Uninterned(n) => Cow::Owned(format!("g{}", n)),
+        }
+    }
+
+    /// Whether this symbol is in the symbol table, so that reading its name
+    /// gives it back.
+    pub fn is_interned(self) -> bool {
+        matches!(self.0, Name::Interned(_))
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        match (self, other) {
+            (Name::Interned(a), Name::Interned(b)) => std::ptr::eq(*a, *b),
+            (Name::Uninterned(a), Name::Uninterned(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Name::Interned(name) => (name.as_ptr() as usize).hash(state),
+            Name::Uninterned(n) => n.hash(state),
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gensym_differs_from_interned_name() {
+        let symbol = Symbol::gensym();
+        assert_ne!(symbol, Symbol::gensym());
+        assert_ne!(symbol, Symbol::intern(&symbol.name()));
+        assert!(!symbol.is_interned());
+        assert_eq!(Symbol::intern("g"), Symbol::intern("g"));
+    }
+}
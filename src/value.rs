@@ -1,58 +1,188 @@
 use crate::env::Env;
+use crate::error::LispError;
 use crate::eval::VM;
+use crate::lexer::Span;
+use crate::number::Number;
+use crate::printer::{Sharing, Written};
+use crate::symbol::Symbol;
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use num_bigint::BigInt;
+use num_rational::BigRational;
 
-pub type BuiltinFn = fn(&mut VM) -> Result<(), String>;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+pub type BuiltinFn = fn(&mut VM) -> Result<(), LispError>;
 
 #[derive(Clone)]
 pub enum Value {
     Null,
     Cons(RefValue, RefValue),
     Bool(bool),
+    Int(i64),
+    Big(Rc<BigInt>),
+    Rat(Rc<BigRational>),
     Num(f64),
-    Ident(String),
+    Str(String),
+    Char(char),
+    Vector(Rc<RefCell<Vec<Value>>>),
+    Bytevector(Rc<RefCell<Vec<u8>>>),
+    Ident(Symbol),
     Syntax(&'static str, BuiltinFn),
     Closure(RefValue, RefValue, Env),
     Subr(&'static str, BuiltinFn),
     Cont(Box<VM>),
 }
 impl Value {
-    pub fn try_into_nil(self) -> Result<(), String> {
+    /// The name of this value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Cons(_, _) => "pair",
+            Value::Bool(_) => "boolean",
+            Value::Int(_) | Value::Big(_) | Value::Rat(_) | Value::Num(_) => "number",
+            Value::Str(_) => "string",
+            Value::Char(_) => "character",
+            Value::Vector(_) => "vector",
+            Value::Bytevector(_) => "bytevector",
+            Value::Ident(_) => "symbol",
+            Value::Syntax(_, _) => "syntax",
+            Value::Closure(_, _, _) | Value::Subr(_, _) | Value::Cont(_) => "procedure",
+        }
+    }
+
+    /// A type error for a place that wanted `expected` but got this value.
+    pub fn type_error(&self, expected: &'static str) -> LispError {
+        LispError::Type {
+            expected,
+            got: self.type_name(),
+        }
+    }
+
+    pub fn try_into_nil(self) -> Result<(), LispError> {
         match self {
             Value::Null => Ok(()),
-            _ => Err("type mismatch".to_string()),
+            _ => Err(self.type_error("null")),
         }
     }
-    pub fn try_into_cons(self) -> Result<(Value, Value), String> {
+    pub fn try_into_cons(self) -> Result<(Value, Value), LispError> {
         match self {
             Value::Cons(car, cdr) => Ok((car.to_value(), cdr.to_value())),
-            _ => Err("type mismatch".to_string()),
+            _ => Err(self.type_error("pair")),
         }
     }
-    pub fn try_into_bool(self) -> Result<bool, String> {
+    pub fn try_into_bool(self) -> Result<bool, LispError> {
         match self {
             Value::Bool(b) => Ok(b),
-            _ => Err("type mismatch".to_string()),
+            _ => Err(self.type_error("boolean")),
+        }
+    }
+    pub fn try_into_number(self) -> Result<Number, LispError> {
+        match self {
+            Value::Int(n) => Ok(Number::Int(n)),
+            Value::Big(n) => Ok(Number::Big(n)),
+            Value::Rat(r) => Ok(Number::Rat(r)),
+            Value::Num(x) => Ok(Number::Real(x)),
+            _ => Err(self.type_error("number")),
+        }
+    }
+    pub fn try_into_int(self) -> Result<i64, LispError> {
+        match self {
+            Value::Int(n) => Ok(n),
+            Value::Big(n) => Err(LispError::InvalidArgument(format!(
+                "integer too large: {}",
+                n
+            ))),
+            _ => Err(self.type_error("exact integer")),
+        }
+    }
+    pub fn try_into_char(self) -> Result<char, LispError> {
+        match self {
+            Value::Char(ch) => Ok(ch),
+            _ => Err(self.type_error("character")),
         }
     }
-    pub fn try_into_num(self) -> Result<f64, String> {
+    pub fn try_into_vector(self) -> Result<Rc<RefCell<Vec<Value>>>, LispError> {
         match self {
-            Value::Num(a) => Ok(a),
-            _ => Err("type mismatch".to_string()),
+            Value::Vector(vector) => Ok(vector),
+            _ => Err(self.type_error("vector")),
         }
     }
-    pub fn try_into_ident(self) -> Result<String, String> {
+    pub fn try_into_bytevector(self) -> Result<Rc<RefCell<Vec<u8>>>, LispError> {
+        match self {
+            Value::Bytevector(bytes) => Ok(bytes),
+            _ => Err(self.type_error("bytevector")),
+        }
+    }
+    pub fn try_into_str(self) -> Result<String, LispError> {
+        match self {
+            Value::Str(string) => Ok(string),
+            _ => Err(self.type_error("string")),
+        }
+    }
+    pub fn try_into_ident(self) -> Result<Symbol, LispError> {
         match self {
             Value::Ident(ident) => Ok(ident),
-            _ => Err("type mismatch".to_string()),
+            _ => Err(self.type_error("symbol")),
+        }
+    }
+
+    pub fn new_vector(values: Vec<Value>) -> Value {
+        Value::Vector(Rc::new(RefCell::new(values)))
+    }
+
+    pub fn new_bytevector(bytes: Vec<u8>) -> Value {
+        Value::Bytevector(Rc::new(RefCell::new(bytes)))
+    }
+
+    /// Mark the pairs of this datum that `parse` read as literal constants
+    /// that `RefValue::set` refuses to change. Pairs built at run time, like
+    /// those a reader macro returns, are left alone, and so are vectors.
+    pub fn mark_literal(&self) {
+        let mut pending = vec![self.clone()];
+        while let Some(value) = pending.pop() {
+            if let Value::Cons(car, cdr) = value {
+                // a marked pair was already visited, which also stops cycles
+                if !car.0.read || car.0.literal.replace(true) {
+                    continue;
+                }
+                cdr.0.literal.set(true);
+                for cell in &[car, cdr] {
+                    if let Value::Cons(_, _) = &*cell.0.value.borrow() {
+                        pending.push(cell.to_value());
+                    }
+                }
+            }
         }
     }
 
     pub fn into_list_iter(self) -> impl Iterator<Item = Value> {
         ListIterator(self)
     }
+
+    /// Build a proper list from `values`.
+    pub fn from_vec(values: Vec<Value>) -> Value {
+        Self::from_vec_with_tail(values, Value::Null)
+    }
+
+    /// Build a list from `values` whose last cdr is `tail`.
+    pub fn from_vec_with_tail(values: Vec<Value>, tail: Value) -> Value {
+        values.into_iter().rev().fold(tail, |acc, value| {
+            Value::Cons(RefValue::new(value), RefValue::new(acc))
+        })
+    }
+}
+
+impl From<Number> for Value {
+    fn from(num: Number) -> Value {
+        match num {
+            Number::Int(n) => Value::Int(n),
+            Number::Big(n) => Value::Big(n),
+            Number::Rat(r) => Value::Rat(r),
+            Number::Real(x) => Value::Num(x),
+        }
+    }
 }
 
 pub struct ListIterator(Value);
@@ -71,30 +201,7 @@ impl Iterator for ListIterator {
 
 impl ::std::fmt::Debug for Value {
     fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
-        match self {
-            Value::Null => write!(f, "()"),
-            Value::Cons(car, cdr) => {
-                write!(f, "({:?}", car.0.borrow())?;
-                fn fmt_l(next: RefValue, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
-                    match *next.0.borrow() {
-                        Value::Cons(ref car, ref cdr) => {
-                            write!(f, " {:?}", car.0.borrow())?;
-                            fmt_l(cdr.clone(), f)
-                        }
-                        Value::Null => write!(f, ")"),
-                        ref other => write!(f, " . {:?})", other),
-                    }
-                }
-                fmt_l(cdr.clone(), f)
-            }
-            Value::Bool(b) => write!(f, "{}", if *b { "#t" } else { "#f" }),
-            Value::Num(num) => write!(f, "{}", num),
-            Value::Ident(ident) => write!(f, "{}", ident),
-            Value::Syntax(name, _) => write!(f, "#<syntax {}>", name),
-            Value::Closure(a, b, _) => write!(f, "#<closure {:?} {:?}>", a, b),
-            Value::Subr(name, _) => write!(f, "#<subr {}>", name),
-            Value::Cont(_vm) => write!(f, "#<subr continuation>"),
-        }
+        write!(f, "{}", Written::new(self, Sharing::Cycles))
     }
 }
 
@@ -104,7 +211,14 @@ impl PartialEq for Value {
             (Value::Null, Value::Null) => true,
             (Value::Cons(car1, cdr1), Value::Cons(car2, cdr2)) => car1 == car2 && cdr1 == cdr2,
             (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
+            (Value::Int(n1), Value::Int(n2)) => n1 == n2,
+            (Value::Big(n1), Value::Big(n2)) => n1 == n2,
+            (Value::Rat(r1), Value::Rat(r2)) => r1 == r2,
             (Value::Num(n1), Value::Num(n2)) => n1 == n2,
+            (Value::Str(s1), Value::Str(s2)) => s1 == s2,
+            (Value::Char(c1), Value::Char(c2)) => c1 == c2,
+            (Value::Vector(v1), Value::Vector(v2)) => Rc::ptr_eq(v1, v2),
+            (Value::Bytevector(b1), Value::Bytevector(b2)) => Rc::ptr_eq(b1, b2),
             (Value::Ident(i1), Value::Ident(i2)) => i1 == i2,
             (Value::Syntax(n1, f1), Value::Syntax(n2, f2)) => n1 == n2 && ::std::ptr::eq(f1, f2),
             (Value::Closure(a1, b1, e1), Value::Closure(a2, b2, e2)) => {
@@ -116,21 +230,104 @@ impl PartialEq for Value {
     }
 }
 
+/// The contents of a `RefValue`.
+struct Slot {
+    value: RefCell<Value>,
+    /// Whether `parse` made this cell, rather than Lisp code.
+    read: bool,
+    /// Whether this is part of a quoted constant, which Lisp code must not
+    /// mutate.
+    literal: Cell<bool>,
+}
+
 #[derive(Clone)]
-pub struct RefValue(Rc<RefCell<Value>>);
+pub struct RefValue(Rc<Slot>);
 impl RefValue {
     pub fn new(value: Value) -> RefValue {
-        RefValue(Rc::new(RefCell::new(value)))
+        RefValue(Rc::new(Slot {
+            value: RefCell::new(value),
+            read: false,
+            literal: Cell::new(false),
+        }))
+    }
+
+    /// A cell of a datum being read by `parse`, which becomes a literal
+    /// constant if the datum is quoted.
+    pub fn new_read(value: Value) -> RefValue {
+        RefValue(Rc::new(Slot {
+            value: RefCell::new(value),
+            read: true,
+            literal: Cell::new(false),
+        }))
     }
 
     pub fn to_value(&self) -> Value {
-        self.0.borrow().clone()
+        self.0.value.borrow().clone()
     }
 
     pub fn replace(&self, value: Value) -> Value {
-        self.0.replace(value)
+        self.0.value.replace(value)
+    }
+
+    /// Replace the value on behalf of Lisp code, which fails if this cell
+    /// is part of a literal constant.
+    pub fn set(&self, value: Value) -> Result<(), LispError> {
+        if self.0.literal.get() {
+            return Err(LispError::InvalidArgument(
+                "attempt to mutate a literal constant".to_string(),
+            ));
+        }
+        self.replace(value);
+        Ok(())
+    }
+
+    /// The address of this cell, which identifies it for as long as it is alive.
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.0) as usize
+    }
+
+    /// Remember that the list starting at this cell was read from `span`.
+    pub fn set_span(&self, span: Span) {
+        SPANS.with(|spans| {
+            let mut spans = spans.borrow_mut();
+            if spans.entries.len() >= spans.next_sweep {
+                spans.entries.retain(|_, (cell, _)| cell.strong_count() > 0);
+                spans.next_sweep = (spans.entries.len() * 2).max(1024);
+            }
+            spans
+                .entries
+                .insert(self.id(), (Rc::downgrade(&self.0), span));
+        });
+    }
+
+    /// The source location of the list starting at this cell, if it was read by `parse`.
+    pub fn span(&self) -> Option<Span> {
+        SPANS.with(|spans| {
+            spans
+                .borrow()
+                .entries
+                .get(&self.id())
+                .map(|(_, span)| span.clone())
+        })
     }
 }
+
+/// Source locations of parsed lists, keyed by the address of their first cell.
+/// Each entry holds a weak pointer so that the address cannot be reused by
+/// another cell while the entry exists; dead entries are swept as the table grows.
+struct SpanTable {
+    entries: HashMap<usize, (Weak<Slot>, Span)>,
+    /// The size at which to sweep next: twice the live entries after the last
+    /// sweep, so that sweeping costs O(1) per insert however many are live.
+    next_sweep: usize,
+}
+
+thread_local! {
+    static SPANS: RefCell<SpanTable> = RefCell::new(SpanTable {
+        entries: HashMap::new(),
+        next_sweep: 1024,
+    });
+}
 impl PartialEq for RefValue {
     fn eq(&self, other: &RefValue) -> bool {
         Rc::ptr_eq(&self.0, &other.0)
@@ -138,6 +335,6 @@ impl PartialEq for RefValue {
 }
 impl ::std::fmt::Debug for RefValue {
     fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
-        write!(f, "{:?}", self.0.borrow())
+        write!(f, "{:?}", self.0.value.borrow())
     }
 }